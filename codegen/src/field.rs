@@ -0,0 +1,302 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{
+    Error, Field, Fields, GenericArgument, Ident, Lit, Meta, NestedMeta, Path, PathArguments,
+    Result, Type,
+};
+
+/// How a field is encoded, selected by its `#[packet(...)]` attribute.
+enum Encoding {
+    /// The `CanIo` implementation of the field type.
+    Default,
+    /// The `CanIo` implementation of `Little<T>`.
+    LittleEndian,
+    /// A `Vec` taking up the rest of the packet, except for the fixed-size fields after it.
+    Remaining,
+    /// A `usize` counting the zero bytes that pad the rest of the packet.
+    PaddingLen,
+    /// A `Vec` with a fixed number of elements.
+    Count(usize),
+    /// The `write`, `read`, `read_async` and `size` functions of a module.
+    With(Path),
+}
+
+/// A field of a packet struct or variant.
+pub struct PacketField<'a> {
+    ident: Option<&'a Ident>,
+    ty: &'a Type,
+    /// The local variable holding the field while the packet is read.
+    var: Ident,
+    encoding: Encoding,
+    /// The element type of `Vec` fields.
+    elem: Option<&'a Type>,
+}
+
+pub fn parse_fields(fields: &Fields) -> Result<Vec<PacketField<'_>>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let encoding = parse_encoding(field)?;
+            let elem = match encoding {
+                Encoding::Remaining | Encoding::Count(_) => Some(vec_elem(&field.ty)?),
+                _ => None,
+            };
+            Ok(PacketField {
+                ident: field.ident.as_ref(),
+                ty: &field.ty,
+                var: Ident::new(&format!("field_{}", i), Span::call_site()),
+                encoding,
+                elem,
+            })
+        })
+        .collect()
+}
+
+fn parse_encoding(field: &Field) -> Result<Encoding> {
+    let attr = match field.attrs.iter().find(|attr| attr.path.is_ident("packet")) {
+        Some(attr) => attr,
+        None => return Ok(Encoding::Default),
+    };
+    let list = match attr.parse_meta()? {
+        Meta::List(list) => list,
+        meta => return Err(Error::new(meta.span(), "Expected #[packet(...)]")),
+    };
+    if list.nested.len() != 1 {
+        return Err(Error::new(
+            list.span(),
+            "Expected exactly one encoding in #[packet(...)]",
+        ));
+    }
+
+    let encoding = match &list.nested[0] {
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("little_endian") => {
+            Encoding::LittleEndian
+        }
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("remaining") => Encoding::Remaining,
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("padding_len") => Encoding::PaddingLen,
+        NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("count") => match &pair.lit {
+            Lit::Int(count) => Encoding::Count(count.base10_parse()?),
+            lit => Err(Error::new(lit.span(), "Expected an integer count"))?,
+        },
+        NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("with") => match &pair.lit {
+            Lit::Str(path) => Encoding::With(path.parse()?),
+            lit => Err(Error::new(lit.span(), "Expected a module path string"))?,
+        },
+        nested => Err(Error::new(
+            nested.span(),
+            "Expected one of little_endian, remaining, padding_len, count = N or with = \"module\"",
+        ))?,
+    };
+    Ok(encoding)
+}
+
+/// Extracts `T` from a field of type `Vec<T>`.
+fn vec_elem(ty: &Type) -> Result<&Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let (true, Some(GenericArgument::Type(elem))) =
+                    (segment.ident == "Vec", args.args.first())
+                {
+                    return Ok(elem);
+                }
+            }
+        }
+    }
+    Err(Error::new(ty.span(), "Expected a Vec<T> field"))
+}
+
+impl<'a> PacketField<'a> {
+    /// Writes the field at `accessor` to `w`.
+    pub fn write(&self, accessor: &TokenStream) -> TokenStream {
+        match &self.encoding {
+            Encoding::Default => quote!(::rakrs_io::CanIo::write(&#accessor, &mut w)?;),
+            Encoding::LittleEndian => {
+                quote!(::rakrs_io::CanIo::write(&::rakrs_io::Little(#accessor), &mut w)?;)
+            }
+            Encoding::Remaining => quote! {
+                for item in #accessor.iter() {
+                    ::rakrs_io::CanIo::write(item, &mut w)?;
+                }
+            },
+            Encoding::PaddingLen => {
+                quote!(::std::io::Write::write_all(&mut w, &::std::vec![0u8; #accessor])?;)
+            }
+            Encoding::Count(count) => {
+                let message = match self.ident {
+                    Some(ident) => format!("Field {} must have {} elements", ident, count),
+                    None => format!("Field must have {} elements", count),
+                };
+                quote! {
+                    if #accessor.len() != #count {
+                        return Err(::std::io::Error::new(
+                            ::std::io::ErrorKind::InvalidInput,
+                            #message,
+                        ));
+                    }
+                    for item in #accessor.iter() {
+                        ::rakrs_io::CanIo::write(item, &mut w)?;
+                    }
+                }
+            }
+            Encoding::With(module) => quote!(#module::write(&#accessor, &mut w)?;),
+        }
+    }
+
+    /// The encoded size of the field at `accessor`.
+    pub fn size(&self, accessor: &TokenStream) -> TokenStream {
+        match &self.encoding {
+            Encoding::Default | Encoding::LittleEndian => {
+                quote!(::rakrs_io::CanIo::size(&#accessor))
+            }
+            Encoding::Remaining | Encoding::Count(_) => {
+                quote!(#accessor.iter().map(::rakrs_io::CanIo::size).sum::<usize>())
+            }
+            Encoding::PaddingLen => quote!(#accessor),
+            Encoding::With(module) => quote!(#module::size(&#accessor)),
+        }
+    }
+
+    /// The encoded size of any value of the field, for fields after a `remaining` field.
+    fn fixed_size(&self) -> Result<TokenStream> {
+        let ty = self.ty;
+        match &self.encoding {
+            Encoding::Default | Encoding::LittleEndian => Ok(quote! {
+                ::rakrs_io::CanIo::size(&<#ty as ::std::default::Default>::default())
+            }),
+            Encoding::With(module) => Ok(quote! {
+                #module::size(&<#ty as ::std::default::Default>::default())
+            }),
+            _ => Err(Error::new(
+                self.ty.span(),
+                "Fields after #[packet(remaining)] must have a fixed size",
+            )),
+        }
+    }
+
+    /// Reads the field from `r` into its variable.
+    ///
+    /// Synchronous readers own `r: impl Read`, while asynchronous readers borrow
+    /// `r: &mut impl AsyncRead`.
+    fn read(&self, asynchronous: bool) -> TokenStream {
+        let var = &self.var;
+        let ty = self.ty;
+        let value = match (&self.encoding, asynchronous) {
+            (Encoding::Default, false) => quote!(<#ty as ::rakrs_io::CanIo>::read(&mut r)?),
+            (Encoding::Default, true) => {
+                quote!(<#ty as ::rakrs_io::AsyncCanIo>::read_async(&mut *r).await?)
+            }
+            (Encoding::LittleEndian, false) => {
+                quote!(<::rakrs_io::Little<#ty> as ::rakrs_io::CanIo>::read(&mut r)?.inner())
+            }
+            (Encoding::LittleEndian, true) => quote! {
+                <::rakrs_io::Little<#ty> as ::rakrs_io::AsyncCanIo>::read_async(&mut *r)
+                    .await?
+                    .inner()
+            },
+            (Encoding::PaddingLen, false) => {
+                quote!(::std::io::copy(&mut r, &mut ::std::io::sink())? as usize)
+            }
+            (Encoding::PaddingLen, true) => quote! {{
+                let mut padding = ::std::vec::Vec::new();
+                ::rakrs_io::read_to_end(&mut *r, &mut padding).await?;
+                padding.len()
+            }},
+            (Encoding::Count(count), _) => {
+                let elem = self.elem;
+                let read = if asynchronous {
+                    quote!(<#elem as ::rakrs_io::AsyncCanIo>::read_async(&mut *r).await?)
+                } else {
+                    quote!(<#elem as ::rakrs_io::CanIo>::read(&mut r)?)
+                };
+                quote! {{
+                    let mut vec = ::std::vec::Vec::with_capacity(#count);
+                    for _ in 0..#count {
+                        vec.push(#read);
+                    }
+                    vec
+                }}
+            }
+            (Encoding::With(module), false) => quote!(#module::read(&mut r)?),
+            (Encoding::With(module), true) => quote!(#module::read_async(&mut *r).await?),
+            (Encoding::Remaining, _) => unreachable!("Remaining fields are read with their tail"),
+        };
+        quote!(let #var: #ty = #value;)
+    }
+
+    /// Reads the rest of `r` into the field, except for the `tail` fields after it, which are
+    /// then read from a new `r` over the tail.
+    fn read_remaining(&self, tail: &[PacketField<'_>], asynchronous: bool) -> Result<TokenStream> {
+        let var = &self.var;
+        let ty = self.ty;
+        let elem = self.elem;
+        let tail_sizes = tail
+            .iter()
+            .map(PacketField::fixed_size)
+            .collect::<Result<Vec<_>>>()?;
+
+        let read_rest = if asynchronous {
+            quote!(::rakrs_io::read_to_end(&mut *r, &mut rest).await?;)
+        } else {
+            quote!(::std::io::Read::read_to_end(&mut r, &mut rest)?;)
+        };
+        let reset = match (tail.is_empty(), asynchronous) {
+            (true, _) => quote!(),
+            (false, false) => quote!(let mut r = tail;),
+            (false, true) => quote! {
+                let mut tail = tail;
+                let r = &mut tail;
+            },
+        };
+
+        Ok(quote! {
+            let mut rest = ::std::vec::Vec::new();
+            #read_rest
+            let tail_size = 0 #(+ #tail_sizes)*;
+            if rest.len() < tail_size {
+                return Err(::rakrs_io::DecodeError::new(
+                    ::rakrs_io::DecodeErrorKind::UnexpectedEof,
+                ).into());
+            }
+            let (head, tail) = rest.split_at(rest.len() - tail_size);
+            let mut head = ::std::io::Cursor::new(head);
+            let mut #var: #ty = ::std::vec::Vec::new();
+            while (head.position() as usize) < head.get_ref().len() {
+                #var.push(<#elem as ::rakrs_io::CanIo>::read(&mut head)?);
+            }
+            #reset
+        })
+    }
+}
+
+/// Reads all fields and constructs the struct or variant at `path` from them.
+pub fn read_fields(
+    path: TokenStream,
+    fields: &Fields,
+    parsed: &[PacketField<'_>],
+    asynchronous: bool,
+) -> Result<TokenStream> {
+    let mut reads = Vec::with_capacity(parsed.len());
+    for (i, field) in parsed.iter().enumerate() {
+        reads.push(match field.encoding {
+            Encoding::Remaining => field.read_remaining(&parsed[i + 1..], asynchronous)?,
+            _ => field.read(asynchronous),
+        });
+    }
+
+    let vars = parsed.iter().map(|field| &field.var);
+    let construct = match fields {
+        Fields::Named(_) => {
+            let idents = parsed.iter().map(|field| field.ident);
+            quote!(#path { #(#idents: #vars),* })
+        }
+        Fields::Unnamed(_) => quote!(#path(#(#vars),*)),
+        Fields::Unit => quote!(#path),
+    };
+    Ok(quote! {{
+        #(#reads)*
+        #construct
+    }})
+}
@@ -3,16 +3,31 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
+mod field;
 mod packet;
 
-/// Generate `rakrs_io::CanIo` implementation for structs and enums that have all fields implement `CanIo`
+/// Generate `rakrs_io::CanIo` and `rakrs_io::AsyncCanIo` implementations for structs and enums that
+/// have all fields implement both traits
 ///
 /// For structs, fields are written one by one in order.
 ///
 /// For enums, the structure starts with a discriminant with the type specified in the `#[repr]` of
 /// the enum, followed by the fields of the enum one by one. If the enum repr should be little
 /// endian, the `#[little_endian]` attribute must be applied on the `enum` item.
-#[proc_macro_derive(Packet, attributes(little_endian))]
+///
+/// The encoding of a field can be changed with one of these attributes:
+/// - `#[packet(little_endian)]`: encoded as `Little<T>`.
+/// - `#[packet(remaining)]`: a `Vec<T>` taking up the rest of the packet, except for the fields
+///   after it, which must implement `Default` and have a fixed size.
+/// - `#[packet(padding_len)]`: a `usize` counting the zero bytes that pad the rest of the packet.
+/// - `#[packet(count = N)]`: a `Vec<T>` with exactly `N` elements.
+/// - `#[packet(with = "module")]`: encoded by `module::write`, `module::read`,
+///   `module::read_async` and `module::size`, which have the signatures of the `CanIo` and
+///   `AsyncCanIo` methods.
+///
+/// Asynchronous reads of `remaining` and `padding_len` fields read until the stream ends, so
+/// packets with such fields should be read from open streams with `rakrs_io::read_frame`.
+#[proc_macro_derive(Packet, attributes(little_endian, packet))]
 pub fn derive_packet(item: TokenStream) -> TokenStream {
     let parsed = parse_macro_input!(item as DeriveInput);
     match packet::imp(parsed) {
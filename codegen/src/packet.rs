@@ -1,24 +1,23 @@
 use proc_macro2::{Literal, Span, TokenStream};
 use quote::quote;
 use syn::spanned::Spanned;
-use syn::{Attribute, Data, DeriveInput, Error, Fields, Ident, Result, Type};
+use syn::{Attribute, Data, DeriveInput, Error, Field, Fields, Ident, Result};
+
+use crate::field::{self, PacketField};
 
 pub fn imp(item: DeriveInput) -> Result<TokenStream> {
     let item_name = &item.ident;
 
-    let (writer, reader) = match &item.data {
+    let (writer, reader, async_reader, sizer) = match &item.data {
         Data::Struct(data) => {
-            let writer = write_fields(
-                &data.fields,
-                |ident| quote!(self.#ident),
-                |i, _| {
-                    let i = Literal::usize_unsuffixed(i);
-                    quote!(self.#i)
-                },
-            )?;
-            let reads = read_fields(&data.fields)?;
-
-            (writer, quote!(Self #reads))
+            let fields = field::parse_fields(&data.fields)?;
+            let accessors = access_struct_fields(&data.fields);
+            let writer = write_fields(&fields, &accessors);
+            let reader = field::read_fields(quote!(#item_name), &data.fields, &fields, false)?;
+            let async_reader = field::read_fields(quote!(#item_name), &data.fields, &fields, true)?;
+            let sizer = size_fields(&fields, &accessors);
+
+            (writer, reader, async_reader, sizer)
         }
         Data::Enum(data) => {
             let endian = match find_attr(&item.attrs, "little_endian") {
@@ -29,19 +28,23 @@ pub fn imp(item: DeriveInput) -> Result<TokenStream> {
             let repr_attr = find_attr(&item.attrs, "repr")
                 .ok_or_else(|| Error::new(item.span(), "Enum packets must declare #[repr]"))?;
             let repr_ty = repr_attr.parse_args::<Ident>()?;
-            let (repr_write, repr_read) = match repr_ty.to_string().as_str() {
-                "u8" => (quote!(write_u8), quote!(read_u8)),
+            let little = find_attr(&item.attrs, "little_endian").is_some();
+            let (repr_write, repr_read, repr_size) = match repr_ty.to_string().as_str() {
+                "u8" => (quote!(write_u8), quote!(read_u8), 1usize),
                 "u16" => (
                     quote!(write_u16::<::byteorder::#endian>),
                     quote!(read_u16::<::byteorder::#endian>),
+                    2,
                 ),
                 "u32" => (
                     quote!(write_u32::<::byteorder::#endian>),
                     quote!(read_u32::<::byteorder::#endian>),
+                    4,
                 ),
                 "u64" => (
                     quote!(write_u64::<::byteorder::#endian>),
                     quote!(read_u64::<::byteorder::#endian>),
+                    8,
                 ),
                 _ => Err(Error::new(
                     repr_attr.tokens.span(),
@@ -49,8 +52,21 @@ pub fn imp(item: DeriveInput) -> Result<TokenStream> {
                 ))?,
             };
 
+            let repr_size = Literal::usize_unsuffixed(repr_size);
+            let repr_read_async = if little && repr_ty != "u8" {
+                quote! {
+                    <::rakrs_io::Little<#repr_ty> as ::rakrs_io::AsyncCanIo>::read_async(&mut *r)
+                        .await?
+                        .inner()
+                }
+            } else {
+                quote!(<#repr_ty as ::rakrs_io::AsyncCanIo>::read_async(&mut *r).await?)
+            };
+
             let mut write_vars = Vec::with_capacity(data.variants.len());
             let mut read_vars = Vec::with_capacity(data.variants.len());
+            let mut async_read_vars = Vec::with_capacity(data.variants.len());
+            let mut size_vars = Vec::with_capacity(data.variants.len());
             for variant in &data.variants {
                 let var_name = &variant.ident;
                 let (_, discrim) = variant.discriminant.as_ref().ok_or_else(|| {
@@ -59,25 +75,25 @@ pub fn imp(item: DeriveInput) -> Result<TokenStream> {
                         "All enum packet variants must have discriminants",
                     )
                 })?;
+                let fields = field::parse_fields(&variant.fields)?;
                 let fields_pat = pat_fields(&variant.fields);
-                let fields_write = write_fields(
-                    &variant.fields,
-                    |ident| {
-                        let ident = Ident::new(&format!("variant_{}", ident), ident.span());
-                        quote!(#ident)
-                    },
-                    |id, span| {
-                        let ident = generate_ident(id, span);
-                        quote!(#ident)
-                    },
-                )?;
-                let fields_read = read_fields(&variant.fields)?;
+                let accessors = access_variant_fields(&variant.fields);
+                let fields_write = write_fields(&fields, &accessors);
+                let var_path = quote!(#item_name::#var_name);
+                let fields_read =
+                    field::read_fields(var_path.clone(), &variant.fields, &fields, false)?;
+                let fields_read_async =
+                    field::read_fields(var_path, &variant.fields, &fields, true)?;
+                let fields_size = size_fields(&fields, &accessors);
 
                 write_vars.push(quote!(#item_name::#var_name #fields_pat => {
                     w.#repr_write(#discrim)?;
                     #fields_write
                 }));
-                read_vars.push(quote!(#discrim => #item_name::#var_name #fields_read));
+                read_vars.push(quote!(#discrim => #fields_read));
+                async_read_vars.push(quote!(#discrim => #fields_read_async));
+                size_vars
+                    .push(quote!(#item_name::#var_name #fields_pat => #repr_size + #fields_size));
             }
 
             let writer = quote! {{
@@ -91,11 +107,28 @@ pub fn imp(item: DeriveInput) -> Result<TokenStream> {
                 let id = r.#repr_read()?;
                 match id {
                     #(#read_vars,)*
-                    _ => Err(::std::io::Error::new(::std::io::ErrorKind::Other, format!("Unexpected enum variant {:?}", id)))?,
+                    _ => Err(::rakrs_io::DecodeError::new(
+                        ::rakrs_io::DecodeErrorKind::UnknownVariant(id.into()),
+                    ))?,
+                }
+            }};
+            let async_reader = quote! {{
+                let id = #repr_read_async;
+                match id {
+                    #(#async_read_vars,)*
+                    _ => Err(::rakrs_io::DecodeError::new(
+                        ::rakrs_io::DecodeErrorKind::UnknownVariant(id.into()),
+                    ))?,
                 }
             }};
 
-            (writer, reader)
+            let sizer = quote! {
+                match self {
+                    #(#size_vars),*
+                }
+            };
+
+            (writer, reader, async_reader, sizer)
         }
         _ => Err(Error::new(
             item.span(),
@@ -103,6 +136,7 @@ pub fn imp(item: DeriveInput) -> Result<TokenStream> {
         ))?,
     };
 
+    let item_str = item_name.to_string();
     let ret = quote! {
         #[automatically_derived]
         impl ::rakrs_io::CanIo for #item_name {
@@ -111,8 +145,37 @@ pub fn imp(item: DeriveInput) -> Result<TokenStream> {
                 Ok(())
             }
 
-            fn read<R: ::std::io::Read>(mut r: R) -> ::std::io::Result<Self> {
-                Ok(#reader)
+            fn read<R: ::std::io::Read>(r: R) -> ::std::io::Result<Self> {
+                let read = |mut r: R| -> ::std::io::Result<Self> { Ok(#reader) };
+                read(r).map_err(|err| ::rakrs_io::DecodeError::with_packet(err, #item_str))
+            }
+
+            fn size(&self) -> usize {
+                #sizer
+            }
+        }
+
+        #[automatically_derived]
+        impl ::rakrs_io::AsyncCanIo for #item_name {
+            fn read_async<'a, R>(
+                r: &'a mut R,
+            ) -> impl ::std::future::Future<Output = ::std::io::Result<Self>> + Send + 'a
+            where
+                R: ::rakrs_io::AsyncRead + Unpin + Send + 'a,
+            {
+                #[allow(unused_variables)]
+                async fn read<R>(r: &mut R) -> ::std::io::Result<#item_name>
+                where
+                    R: ::rakrs_io::AsyncRead + Unpin + Send,
+                {
+                    Ok(#async_reader)
+                }
+
+                async move {
+                    read(r)
+                        .await
+                        .map_err(|err| ::rakrs_io::DecodeError::with_packet(err, #item_str))
+                }
             }
         }
     };
@@ -124,111 +187,73 @@ where
     I: IntoIterator<Item = &'a Attribute>,
     S: AsRef<str>,
 {
-    attr.into_iter()
-        .filter(|attr| attr.path.is_ident(&name))
-        .next()
+    attr.into_iter().find(|attr| attr.path.is_ident(&name))
 }
 
-fn write_fields<F, G>(fields: &Fields, access_named: F, access_unnamed: G) -> Result<TokenStream>
-where
-    F: Fn(&Ident) -> TokenStream,
-    G: Fn(usize, Span) -> TokenStream,
-{
-    let ret = match fields {
-        Fields::Named(fields) => {
-            let fields: Vec<TokenStream> = fields
-                .named
-                .iter()
-                .map(|field| {
-                    let ident = field.ident.as_ref().unwrap();
-                    let accessor = access_named(ident);
-                    write_field(&field.ty, &accessor)
-                })
-                .collect::<Result<_>>()?;
-            quote!(#(#fields)*)
-        }
-        Fields::Unnamed(fields) => {
-            let fields: Vec<TokenStream> = fields
-                .unnamed
-                .iter()
-                .enumerate()
-                .map(|(i, field)| {
-                    let accessor = access_unnamed(i, field.span());
-                    write_field(&field.ty, &accessor)
-                })
-                .collect::<Result<_>>()?;
-            quote!(#(#fields)*)
-        }
-        Fields::Unit => quote!(),
-    };
-    Ok(ret)
+/// Accesses each field as `self.member` in structs.
+fn access_struct_fields(fields: &Fields) -> Vec<TokenStream> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => quote!(self.#ident),
+            None => {
+                let i = Literal::usize_unsuffixed(i);
+                quote!(self.#i)
+            }
+        })
+        .collect()
 }
 
-fn read_fields(fields: &Fields) -> Result<TokenStream> {
-    let ret = match fields {
-        Fields::Named(fields) => {
-            let fields: Vec<TokenStream> = fields
-                .named
-                .iter()
-                .map(|field| {
-                    let field_name = &field.ident;
-                    let read_expr = read_field(&field.ty)?;
-                    Ok(quote!(#field_name: #read_expr))
-                })
-                .collect::<Result<_>>()?;
-            quote!({ #(#fields),* })
-        }
-        Fields::Unnamed(fields) => {
-            let fields: Vec<TokenStream> = fields
-                .unnamed
-                .iter()
-                .map(|field| {
-                    let read_expr = read_field(&field.ty)?;
-                    Ok(quote!(#read_expr))
-                })
-                .collect::<Result<_>>()?;
-            quote!(( #(#fields),* ))
-        }
-        Fields::Unit => quote!(),
-    };
-    Ok(ret)
+/// Accesses each field of an enum variant through the bindings of `pat_fields`.
+fn access_variant_fields(fields: &Fields) -> Vec<TokenStream> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let binding = bind_field(i, field);
+            // the pattern binds fields by reference
+            quote!((*#binding))
+        })
+        .collect()
 }
 
-fn pat_fields(fields: &Fields) -> TokenStream {
-    match fields {
-        Fields::Named(fields) => {
-            let fields = fields
-                .named
-                .iter()
-                .map(|field| field.ident.as_ref().unwrap());
-            quote!({ #(#fields),* })
-        }
-        Fields::Unnamed(fields) => {
-            let fields = fields
-                .unnamed
-                .iter()
-                .enumerate()
-                .map(|(i, field)| generate_ident(i, field.span()));
-            quote!(( #(#fields),* ))
-        }
-        Fields::Unit => quote!(),
+fn bind_field(i: usize, field: &Field) -> Ident {
+    match &field.ident {
+        Some(ident) => Ident::new(&format!("variant_{}", ident), ident.span()),
+        None => generate_ident(i, field.span()),
     }
 }
 
-fn write_field(_ty: &Type, expr: &TokenStream) -> Result<TokenStream> {
-    Ok(quote! {{
-        use ::rakrs_io::CanIo;
-        (#expr).write(&mut w)?;
-    }})
+fn write_fields(fields: &[PacketField<'_>], accessors: &[TokenStream]) -> TokenStream {
+    let writes = fields
+        .iter()
+        .zip(accessors)
+        .map(|(field, accessor)| field.write(accessor));
+    quote!(#(#writes)*)
 }
 
-fn read_field(ty: &Type) -> Result<TokenStream> {
-    Ok(quote! {
-        {
-            let var: #ty = ::rakrs_io::CanIo::read(&mut r)?;
-            var
+fn size_fields(fields: &[PacketField<'_>], accessors: &[TokenStream]) -> TokenStream {
+    let sizes = fields
+        .iter()
+        .zip(accessors)
+        .map(|(field, accessor)| field.size(accessor));
+    quote!(0 #(+ #sizes)*)
+}
+
+fn pat_fields(fields: &Fields) -> TokenStream {
+    let bindings = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| bind_field(i, field));
+    match fields {
+        Fields::Named(_) => {
+            let idents = fields.iter().map(|field| field.ident.as_ref().unwrap());
+            quote!({ #(#idents: #bindings),* })
         }
-    })
+        Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+        Fields::Unit => quote!(),
+    }
 }
 
 fn generate_ident(i: usize, span: Span) -> Ident {
@@ -0,0 +1,292 @@
+use std::future::{self, Future};
+use std::io::{Cursor, Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+pub use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{CanIo, DecodeError, DecodeErrorKind, Little, Triad};
+
+/// Allows the type to be encoded/decoded using RakNet binary format over tokio streams.
+///
+/// Values are written from a buffer of exactly `CanIo::size` bytes, so implementations only need
+/// to read asynchronously.
+///
+/// `read_async` reads directly from the stream, which only works for types whose encoding tells
+/// where it ends. Packets with `#[packet(remaining)]` or `#[packet(padding_len)]` fields, such as
+/// `OpenConnectionRequest1` and `NewIncomingConnection`, and enums containing them, read until the
+/// stream ends and never return on a live connection. Streams carrying such values should use
+/// `write_frame` and `read_frame` instead.
+pub trait AsyncCanIo: CanIo + Send + Sync {
+    fn read_async<'a, R>(r: &'a mut R) -> impl Future<Output = Result<Self>> + Send + 'a
+    where
+        R: AsyncRead + Unpin + Send + 'a;
+
+    fn write_async<'a, W>(&'a self, w: &'a mut W) -> impl Future<Output = Result<()>> + Send + 'a
+    where
+        W: AsyncWrite + Unpin + Send + 'a,
+    {
+        async move { write_all(w, &self.encode_to_vec()).await }
+    }
+}
+
+/// Fills `buf` from the stream, failing with `ErrorKind::UnexpectedEof` if it ends first.
+pub async fn read_exact<R: AsyncRead + Unpin>(r: &mut R, buf: &mut [u8]) -> Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let read = future::poll_fn(|cx| Pin::new(&mut *r).poll_read(cx, rest)).await?;
+        if read == 0 {
+            return Err(DecodeError::new(DecodeErrorKind::UnexpectedEof).into());
+        }
+        filled += read;
+    }
+    Ok(())
+}
+
+/// Reads the stream until it ends.
+pub async fn read_to_end<R: AsyncRead + Unpin>(r: &mut R, buf: &mut Vec<u8>) -> Result<()> {
+    let mut chunk = [0u8; 1024];
+    loop {
+        let read = future::poll_fn(|cx| Pin::new(&mut *r).poll_read(cx, &mut chunk)).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// Writes the whole of `buf` to the stream.
+pub async fn write_all<W: AsyncWrite + Unpin>(w: &mut W, buf: &[u8]) -> Result<()> {
+    let mut written = 0;
+    while written < buf.len() {
+        let rest = &buf[written..];
+        let write = future::poll_fn(|cx| Pin::new(&mut *w).poll_write(cx, rest)).await?;
+        if write == 0 {
+            return Err(Error::new(
+                ErrorKind::WriteZero,
+                "Stream accepts no more data",
+            ));
+        }
+        written += write;
+    }
+    Ok(())
+}
+
+/// Reads a value of a known encoded size.
+pub async fn read_sized<T: CanIo, R: AsyncRead + Unpin>(r: &mut R, size: usize) -> Result<T> {
+    let mut buf = vec![0u8; size];
+    read_exact(r, &mut buf).await?;
+    T::read(&buf[..])
+}
+
+/// Reads a value that extends to the end of the stream, like the synchronous `CanIo::read` of
+/// some packets does.
+pub async fn read_remaining<T: CanIo, R: AsyncRead + Unpin>(r: &mut R) -> Result<T> {
+    let mut buf = vec![];
+    read_to_end(r, &mut buf).await?;
+    T::read(&buf[..])
+}
+
+/// Writes a value prefixed with its encoded size as a big-endian `u32`.
+pub async fn write_frame<T: CanIo, W: AsyncWrite + Unpin>(w: &mut W, value: &T) -> Result<()> {
+    let size = value.size();
+    let mut buf = Vec::with_capacity(4 + size);
+    (size as u32).write(&mut buf)?;
+    value.write(&mut buf)?;
+    write_all(w, &buf).await?;
+    future::poll_fn(|cx| Pin::new(&mut *w).poll_flush(cx)).await
+}
+
+/// Reads a value written by `write_frame`.
+///
+/// Frames larger than `max_size` are rejected with `ErrorKind::InvalidData` before their body is
+/// read. The value is decoded with `CanIo::read` and must take up the whole frame.
+pub async fn read_frame<T: CanIo, R: AsyncRead + Unpin>(r: &mut R, max_size: usize) -> Result<T> {
+    let size = read_sized::<u32, _>(r, 4).await? as usize;
+    if size > max_size {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Frame of {} bytes exceeds the limit of {}", size, max_size),
+        ));
+    }
+    let mut buf = vec![0u8; size];
+    read_exact(r, &mut buf).await?;
+
+    let mut cursor = Cursor::new(&buf[..]);
+    let value = T::read(&mut cursor)?;
+    let trailing = size - cursor.position() as usize;
+    if trailing > 0 {
+        return Err(DecodeError::new(DecodeErrorKind::TrailingBytes(trailing)).into());
+    }
+    Ok(value)
+}
+
+macro_rules! impl_sized {
+    ($($ty:ty, $size:literal;)*) => {
+        $(
+            impl AsyncCanIo for $ty {
+                async fn read_async<'a, R>(r: &'a mut R) -> Result<Self>
+                where
+                    R: AsyncRead + Unpin + Send + 'a,
+                {
+                    read_sized(r, $size).await
+                }
+            }
+        )*
+    };
+}
+
+impl_sized! {
+    bool, 1;
+    u8, 1;
+    i8, 1;
+    u16, 2;
+    u32, 4;
+    u64, 8;
+    i16, 2;
+    i32, 4;
+    i64, 8;
+    f32, 4;
+    f64, 8;
+    Triad, 3;
+    Little<u16>, 2;
+    Little<u32>, 4;
+    Little<u64>, 8;
+    Little<i16>, 2;
+    Little<i32>, 4;
+    Little<i64>, 8;
+    Little<f32>, 4;
+    Little<f64>, 8;
+    Little<Triad>, 3;
+}
+
+impl AsyncCanIo for String {
+    async fn read_async<'a, R>(r: &'a mut R) -> Result<Self>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        let len = u16::read_async(r).await?;
+        let mut buf = vec![0u8; len as usize];
+        read_exact(r, &mut buf).await?;
+        String::from_utf8(buf).map_err(|_| DecodeError::new(DecodeErrorKind::InvalidUtf8).into())
+    }
+}
+
+impl AsyncCanIo for SocketAddr {
+    async fn read_async<'a, R>(r: &'a mut R) -> Result<Self>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        let version = u8::read_async(r).await?;
+        let rest = match version {
+            4 => 4 + 2,
+            6 => 2 + 2 + 4 + 16 + 4,
+            _ => Err(DecodeError::new(DecodeErrorKind::UnsupportedIpVersion(
+                version,
+            )))?,
+        };
+        let mut buf = vec![0u8; 1 + rest];
+        buf[0] = version;
+        read_exact(r, &mut buf[1..]).await?;
+        SocketAddr::read(&buf[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn decode_kind(err: Error) -> DecodeErrorKind {
+        DecodeError::from(err).kind()
+    }
+
+    #[test]
+    fn test_read_exact() {
+        block_on(async {
+            let mut buf = [0u8; 2];
+            read_exact(&mut &[1, 2, 3][..], &mut buf).await.unwrap();
+            assert_eq!(buf, [1, 2]);
+
+            let mut buf = [0u8; 3];
+            let err = read_exact(&mut &[1, 2][..], &mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        });
+    }
+
+    #[test]
+    fn test_write_all() {
+        block_on(async {
+            let mut buf = [0u8; 2];
+            let mut w = Cursor::new(&mut buf[..]);
+            let err = write_all(&mut w, &[1, 2, 3]).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WriteZero);
+            assert_eq!(buf, [1, 2]);
+        });
+    }
+
+    #[test]
+    fn test_frame() {
+        block_on(async {
+            let mut buf = vec![];
+            write_frame(&mut buf, &String::from("rakrs")).await.unwrap();
+            assert_eq!(&buf[..4], &[0, 0, 0, 7]);
+            let value: String = read_frame(&mut &buf[..], 7).await.unwrap();
+            assert_eq!(value, "rakrs");
+
+            let err = read_frame::<String, _>(&mut &buf[..], 6).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+            let trailing = [0, 0, 0, 3, 0x12, 0x34, 0x56];
+            let err = read_frame::<u16, _>(&mut &trailing[..], 3)
+                .await
+                .unwrap_err();
+            assert_eq!(decode_kind(err), DecodeErrorKind::TrailingBytes(1));
+
+            let short = [0, 0, 0, 3, 0x12];
+            let err = read_frame::<u16, _>(&mut &short[..], 3).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        });
+    }
+
+    #[test]
+    fn test_string() {
+        block_on(async {
+            let buf = [0x00, 0x03, b'a', b'b', b'c', 0xff];
+            let mut r = &buf[..];
+            assert_eq!(String::read_async(&mut r).await.unwrap(), "abc");
+            assert_eq!(r, [0xff]);
+
+            let err = String::read_async(&mut &[0x00, 0x01, 0xff][..])
+                .await
+                .unwrap_err();
+            assert_eq!(decode_kind(err), DecodeErrorKind::InvalidUtf8);
+        });
+    }
+
+    #[test]
+    fn test_socket_addr() {
+        block_on(async {
+            for addr in &["127.0.0.1:19132", "[::1]:19132"] {
+                let addr: SocketAddr = addr.parse().unwrap();
+                let buf = addr.encode_to_vec();
+                let mut r = &buf[..];
+                assert_eq!(SocketAddr::read_async(&mut r).await.unwrap(), addr);
+                assert!(r.is_empty());
+            }
+
+            let err = SocketAddr::read_async(&mut &[5, 0, 0][..])
+                .await
+                .unwrap_err();
+            assert_eq!(decode_kind(err), DecodeErrorKind::UnsupportedIpVersion(5));
+        });
+    }
+}
@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use derive_more::Display;
+
+/// Describes what failed while decoding a value.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Hash)]
+pub enum DecodeErrorKind {
+    #[display(fmt = "Unexpected end of input")]
+    UnexpectedEof,
+    #[display(fmt = "Received invalid value {} for bool", _0)]
+    InvalidBool(u8),
+    #[display(fmt = "Received invalid UTF-8 string")]
+    InvalidUtf8,
+    #[display(fmt = "Received unsupported IP version {}", _0)]
+    UnsupportedIpVersion(u8),
+    #[display(fmt = "Magic payload mismatch")]
+    MagicMismatch,
+    #[display(fmt = "Unexpected enum variant {}", _0)]
+    UnknownVariant(u64),
+    #[display(fmt = "Unexpected record type {}", _0)]
+    UnknownRecordType(u8),
+    #[display(fmt = "Inner packet payload length is zero")]
+    EmptyPayload,
+    #[display(fmt = "Frame has {} trailing bytes", _0)]
+    TrailingBytes(usize),
+    /// The reader failed for a reason unrelated to the data.
+    #[display(fmt = "I/O error: {:?}", _0)]
+    Io(io::ErrorKind),
+}
+
+/// Indicates that a value could not be decoded.
+///
+/// `CanIo::read` returns this wrapped in an `io::Error`, which can be converted back with
+/// `DecodeError::from`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    kind: DecodeErrorKind,
+    pub(crate) offset: Option<usize>,
+    packet: Option<&'static str>,
+}
+
+impl DecodeError {
+    pub fn new(kind: DecodeErrorKind) -> Self {
+        Self {
+            kind,
+            offset: None,
+            packet: None,
+        }
+    }
+
+    /// What failed.
+    pub fn kind(&self) -> DecodeErrorKind {
+        self.kind
+    }
+
+    /// The offset in the decoded buffer where decoding stopped, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// The name of the innermost packet type being decoded, if known.
+    pub fn packet(&self) -> Option<&'static str> {
+        self.packet
+    }
+
+    /// Records the packet type being decoded, unless an inner packet has already been recorded.
+    pub fn with_packet(err: io::Error, packet: &'static str) -> io::Error {
+        let mut err = Self::from(err);
+        err.packet.get_or_insert(packet);
+        err.into()
+    }
+
+    /// Shifts the recorded offset by the size of the data preceding the decoded buffer.
+    pub fn with_offset(err: io::Error, base: usize) -> io::Error {
+        let mut err = Self::from(err);
+        err.offset = Some(err.offset.unwrap_or(0) + base);
+        err.into()
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(packet) = self.packet {
+            write!(f, " in {}", packet)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for DecodeError {}
+
+impl From<DecodeErrorKind> for DecodeError {
+    fn from(kind: DecodeErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(err: io::Error) -> Self {
+        let kind = err.kind();
+        match err
+            .into_inner()
+            .map(|inner| inner.downcast::<DecodeError>())
+        {
+            Some(Ok(err)) => *err,
+            _ if kind == io::ErrorKind::UnexpectedEof => Self::new(DecodeErrorKind::UnexpectedEof),
+            _ => Self::new(DecodeErrorKind::Io(kind)),
+        }
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> Self {
+        let kind = match err.kind {
+            DecodeErrorKind::UnexpectedEof => io::ErrorKind::UnexpectedEof,
+            DecodeErrorKind::Io(kind) => kind,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -1,11 +1,18 @@
-use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Write};
 use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
 
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 
+pub use async_io::{
+    read_exact, read_frame, read_remaining, read_sized, read_to_end, write_all, write_frame,
+    AsyncCanIo, AsyncRead, AsyncWrite,
+};
+pub use error::{DecodeError, DecodeErrorKind};
 pub use little::Little;
 pub use triad::Triad;
 
+mod async_io;
+mod error;
 mod little;
 mod triad;
 
@@ -14,6 +21,42 @@ pub trait CanIo: Sized {
     fn write<W: Write>(&self, w: W) -> Result<()>;
 
     fn read<R: Read>(r: R) -> Result<Self>;
+
+    /// The exact number of bytes written by `write`.
+    fn size(&self) -> usize;
+
+    /// Decodes a value from the start of `buf`, recording the offset where decoding failed.
+    fn decode(buf: &[u8]) -> std::result::Result<Self, DecodeError> {
+        let mut cursor = Cursor::new(buf);
+        Self::read(&mut cursor).map_err(|err| {
+            let mut err = DecodeError::from(err);
+            err.offset.get_or_insert(cursor.position() as usize);
+            err
+        })
+    }
+
+    /// Encodes the value into a new buffer of exactly the encoded size.
+    fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size());
+        self.write(&mut buf)
+            .expect("Writing to Vec<u8> never fails");
+        buf
+    }
+
+    /// Encodes the value at the start of `buf`, returning the number of bytes written.
+    ///
+    /// Fails with `ErrorKind::WriteZero` without writing anything if `buf` is too short.
+    fn encode_into(&self, buf: &mut [u8]) -> Result<usize> {
+        let size = self.size();
+        if buf.len() < size {
+            return Err(Error::new(
+                ErrorKind::WriteZero,
+                format!("Buffer of {} bytes cannot hold {} bytes", buf.len(), size),
+            ));
+        }
+        self.write(&mut buf[..size])?;
+        Ok(size)
+    }
 }
 
 /// Binary representation of a bool.
@@ -26,12 +69,13 @@ impl CanIo for bool {
         match r.read_u8()? {
             0 => Ok(false),
             1 => Ok(true),
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                "Received invalid value for bool",
-            )),
+            value => Err(DecodeError::new(DecodeErrorKind::InvalidBool(value)).into()),
         }
     }
+
+    fn size(&self) -> usize {
+        1
+    }
 }
 
 /// Binary representation of an unsigned byte.
@@ -43,6 +87,10 @@ impl CanIo for u8 {
     fn read<R: Read>(mut r: R) -> Result<Self> {
         r.read_u8()
     }
+
+    fn size(&self) -> usize {
+        1
+    }
 }
 
 /// Binary representation of a signed byte.
@@ -54,13 +102,17 @@ impl CanIo for i8 {
     fn read<R: Read>(mut r: R) -> Result<Self> {
         r.read_i8()
     }
+
+    fn size(&self) -> usize {
+        1
+    }
 }
 
 macro_rules! impl_primitive {
-    ($ty:ty, $write:ident, $read:ident) => {
-        impl_primitive!($ty, $ty, $write, $read);
+    ($ty:ty, $size:literal, $write:ident, $read:ident) => {
+        impl_primitive!($ty, $ty, $size, $write, $read);
     };
-    ($ty:ty, $intermediate:ty, $write:ident, $read:ident) => {
+    ($ty:ty, $intermediate:ty, $size:literal, $write:ident, $read:ident) => {
         /// Binary representation in big-endian.
         ///
         /// Wrap the type with `Little` to encode in little-endian.
@@ -74,6 +126,10 @@ macro_rules! impl_primitive {
                 let value = r.$read::<BigEndian>()?;
                 Ok(value.into())
             }
+
+            fn size(&self) -> usize {
+                $size
+            }
         }
 
         /// Binary representation in little-endian.
@@ -88,19 +144,23 @@ macro_rules! impl_primitive {
                 let intermediate = <$intermediate>::from(raw);
                 Ok(Little::from(intermediate))
             }
+
+            fn size(&self) -> usize {
+                $size
+            }
         }
     };
 }
 
-impl_primitive!(u16, write_u16, read_u16);
-impl_primitive!(u32, write_u32, read_u32);
-impl_primitive!(u64, write_u64, read_u64);
-impl_primitive!(i16, write_i16, read_i16);
-impl_primitive!(i32, write_i32, read_i32);
-impl_primitive!(i64, write_i64, read_i64);
-impl_primitive!(f32, write_f32, read_f32);
-impl_primitive!(f64, write_f64, read_f64);
-impl_primitive!(Triad, write_u24, read_u24);
+impl_primitive!(u16, 2, write_u16, read_u16);
+impl_primitive!(u32, 4, write_u32, read_u32);
+impl_primitive!(u64, 8, write_u64, read_u64);
+impl_primitive!(i16, 2, write_i16, read_i16);
+impl_primitive!(i32, 4, write_i32, read_i32);
+impl_primitive!(i64, 8, write_i64, read_i64);
+impl_primitive!(f32, 4, write_f32, read_f32);
+impl_primitive!(f64, 8, write_f64, read_f64);
+impl_primitive!(Triad, 3, write_u24, read_u24);
 
 /// Encodes a string using a u16 prefix indicating the length, followed by the characters encoded
 /// in UTF-8.
@@ -116,13 +176,19 @@ impl CanIo for String {
         r.read_exact(&mut buf)?;
         match String::from_utf8(buf) {
             Ok(string) => Ok(string),
-            Err(err) => Err(Error::new(ErrorKind::Other, err)),
+            Err(_) => Err(DecodeError::new(DecodeErrorKind::InvalidUtf8).into()),
         }
     }
+
+    fn size(&self) -> usize {
+        2 + self.len()
+    }
 }
 
 /// Encodes an IP address + port using RakNet format. This is a mix of standard and non-standard
 /// IP encoding.
+///
+/// The octets of IPv4 addresses are bitwise inverted on the wire, and restored when read.
 impl CanIo for SocketAddr {
     fn write<W: Write>(&self, mut w: W) -> Result<()> {
         match self {
@@ -151,6 +217,9 @@ impl CanIo for SocketAddr {
             4 => {
                 let mut bytes = [0u8; 4];
                 r.read_exact(&mut bytes)?;
+                for byte in &mut bytes {
+                    *byte = !*byte;
+                }
                 let port = u16::read(&mut r)?;
                 SocketAddr::V4(SocketAddrV4::new(bytes.into(), port))
             }
@@ -163,11 +232,38 @@ impl CanIo for SocketAddr {
                 let scope_id = u32::read(&mut r)?;
                 SocketAddr::V6(SocketAddrV6::new(bytes.into(), port, flow_info, scope_id))
             }
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                "Received unsupported IP version",
-            ))?,
+            version => Err(DecodeError::new(DecodeErrorKind::UnsupportedIpVersion(
+                version,
+            )))?,
         };
         Ok(ret)
     }
+
+    fn size(&self) -> usize {
+        match self {
+            SocketAddr::V4(_) => 1 + 4 + 2,
+            SocketAddr::V6(_) => 1 + 2 + 2 + 4 + 16 + 4,
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate self as rakrs_io;
+
+#[cfg(test)]
+rakrs_testkit::canio_ok! {
+    test_read_v4:
+        0x04, 0x80, 0xff, 0xff, 0xfe, 0x4a, 0xbc,
+    = test_write_v4: "127.0.0.1:19132".parse::<SocketAddr>().unwrap()
+}
+
+#[cfg(test)]
+rakrs_testkit::canio_ok! {
+    test_read_v6:
+        0x06, 0x0a, 0x00, 0x4a, 0xbc,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+    = test_write_v6: "[::1]:19132".parse::<SocketAddr>().unwrap()
 }
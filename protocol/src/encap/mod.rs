@@ -14,6 +14,14 @@ macro_rules! packets {
         pub enum EncapPacket {
             $($name($mod::$name) = $id),*
         }
+
+        impl EncapPacket {
+            /// Checks whether a payload starting with `id` is an `EncapPacket` rather than a
+            /// message for the application.
+            pub fn is_encap_id(id: u8) -> bool {
+                matches!(id, $($id)|*)
+            }
+        }
     };
 }
 
@@ -1,60 +1,36 @@
-use std::io::{Cursor, Error, ErrorKind, Read, Result, Write};
 use std::net::SocketAddr;
 
-use rakrs_io::CanIo;
-
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, rakrs_codegen::Packet, PartialEq)]
 pub struct NewIncomingConnection {
     pub address: SocketAddr,
+    #[packet(remaining)]
     pub system_addresses: Vec<SocketAddr>,
     pub send_ping_time: u64,
     pub send_pong_time: u64,
 }
 
-impl CanIo for NewIncomingConnection {
-    fn write<W: Write>(&self, mut w: W) -> Result<()> {
-        self.address.write(&mut w)?;
-        for addr in &self.system_addresses {
-            addr.write(&mut w)?;
-        }
-        self.send_ping_time.write(&mut w)?;
-        self.send_pong_time.write(&mut w)?;
-        Ok(())
+#[cfg(test)]
+rakrs_testkit::canio_ok! {
+    test_read:
+        0x04, 0x80, 0xff, 0xff, 0xfe, 0x4a, 0xbc,
+        0x04, 0xf5, 0xff, 0xff, 0xfe, 0x4a, 0xbc,
+        0x04, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    = test_write: NewIncomingConnection {
+        address: "127.0.0.1:19132".parse().unwrap(),
+        system_addresses: vec![
+            "10.0.0.1:19132".parse().unwrap(),
+            "0.0.0.0:0".parse().unwrap(),
+        ],
+        send_ping_time: 1,
+        send_pong_time: 2,
     }
+}
 
-    fn read<R: Read>(mut r: R) -> Result<Self> {
-        let address = <SocketAddr as CanIo>::read(&mut r)?;
-
-        let mut buf = vec![];
-        r.read_to_end(&mut buf)?; // a bit hacky
-        drop(r);
-
-        if buf.len() < 16 {
-            return Err(Error::new(
-                ErrorKind::UnexpectedEof,
-                "Expected send_ping_time and send_pong_time",
-            ));
-        }
-
-        let sa_len = buf.len() - 16;
-        let mut cursor = Cursor::new(&buf[sa_len..buf.len()]);
-        let send_ping_time: u64 = CanIo::read(&mut cursor)?;
-        let send_pong_time: u64 = CanIo::read(&mut cursor)?;
-
-        let mut cursor = Cursor::new(&buf[0..sa_len]);
-        let mut system_addresses = vec![];
-        while (cursor.position() as usize) < sa_len {
-            // Triggers whenever there is at least one remaining byte
-            // so that UnexpectedEof will be thrown
-            let addr = <SocketAddr as CanIo>::read(&mut cursor)?;
-            system_addresses.push(addr);
-        }
-
-        Ok(Self {
-            address,
-            system_addresses,
-            send_ping_time,
-            send_pong_time,
-        })
-    }
+#[cfg(test)]
+rakrs_testkit::canio_err_read! {
+    test_short_read: NewIncomingConnection => rakrs_io::DecodeErrorKind::UnexpectedEof;
+        0x04, 0x80, 0xff, 0xff, 0xfe, 0x4a, 0xbc,
+        0x00, 0x00, 0x00, 0x01,
 }
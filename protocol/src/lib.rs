@@ -1,5 +1,3 @@
-#![feature(arbitrary_enum_discriminant, proc_macro_hygiene)]
-
 pub use magic::Magic;
 pub use offline::OfflinePacket;
 pub use online::OnlinePacket;
@@ -8,3 +6,80 @@ pub mod encap;
 mod magic;
 pub mod offline;
 pub mod online;
+
+#[cfg(test)]
+mod tests {
+    use rakrs_io::{AsyncCanIo, CanIo};
+
+    /// Encodes a `u16` in a single byte.
+    mod short {
+        use std::io::{Read, Result, Write};
+
+        use rakrs_io::{AsyncCanIo, AsyncRead, CanIo};
+
+        pub fn write<W: Write>(value: &u16, w: W) -> Result<()> {
+            (*value as u8).write(w)
+        }
+
+        pub fn read<R: Read>(r: R) -> Result<u16> {
+            Ok(u8::read(r)?.into())
+        }
+
+        pub async fn read_async<R: AsyncRead + Unpin + Send>(r: &mut R) -> Result<u16> {
+            Ok(u8::read_async(r).await?.into())
+        }
+
+        pub fn size(_: &u16) -> usize {
+            1
+        }
+    }
+
+    #[derive(Debug, rakrs_codegen::Packet, PartialEq)]
+    struct Attributes {
+        #[packet(little_endian)]
+        little: u16,
+        #[packet(count = 2)]
+        pair: Vec<u8>,
+        #[packet(with = "short")]
+        short: u16,
+        #[packet(remaining)]
+        rest: Vec<u16>,
+        last: u8,
+    }
+
+    fn attributes() -> Attributes {
+        Attributes {
+            little: 0x0102,
+            pair: vec![3, 4],
+            short: 5,
+            rest: vec![6, 7],
+            last: 8,
+        }
+    }
+
+    rakrs_testkit::canio_ok! {
+        test_read:
+            0x02, 0x01,
+            0x03, 0x04,
+            0x05,
+            0x00, 0x06, 0x00, 0x07,
+            0x08,
+        = test_write: attributes()
+    }
+
+    #[test]
+    fn test_attributes() {
+        let buf = attributes().encode_to_vec();
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let read = runtime.block_on(Attributes::read_async(&mut &buf[..]));
+        assert_eq!(read.unwrap(), attributes());
+
+        let mut invalid = attributes();
+        invalid.pair.push(0);
+        let err = invalid.write(&mut vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Field pair must have 2 elements");
+    }
+}
@@ -1,6 +1,6 @@
-use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::io::{Read, Result, Write};
 
-use rakrs_io::CanIo;
+use rakrs_io::{AsyncCanIo, AsyncRead, CanIo, DecodeError, DecodeErrorKind};
 
 /// Handles the 16-byte magic sequence in RakNet protocol.
 /// This is a marker type and does not take any memory.
@@ -21,12 +21,25 @@ impl CanIo for Magic {
     fn read<R: Read>(mut r: R) -> Result<Self> {
         let mut payload = [0u8; 16];
         r.read_exact(&mut payload)?;
-        if &payload == &MAGIC_PAYLOAD {
+        if payload == MAGIC_PAYLOAD {
             Ok(Self)
         } else {
-            Err(Error::new(ErrorKind::Other, "Magic payload mismatch"))
+            Err(DecodeError::new(DecodeErrorKind::MagicMismatch).into())
         }
     }
+
+    fn size(&self) -> usize {
+        MAGIC_PAYLOAD.len()
+    }
+}
+
+impl AsyncCanIo for Magic {
+    async fn read_async<'a, R>(r: &'a mut R) -> Result<Self>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        rakrs_io::read_sized(r, MAGIC_PAYLOAD.len()).await
+    }
 }
 
 #[cfg(test)]
@@ -41,7 +54,7 @@ rakrs_testkit::canio_ok! {
 
 #[cfg(test)]
 rakrs_testkit::canio_err_read! {
-    test_bad_read_1: Magic => "Magic payload mismatch";
+    test_bad_read_1: Magic => DecodeErrorKind::MagicMismatch;
         0x01, 0xff, 0xff, 0x00,
         0xfe, 0xfe, 0xfe, 0xfe,
         0xfd, 0xfd, 0xfd, 0xfd,
@@ -50,7 +63,7 @@ rakrs_testkit::canio_err_read! {
 
 #[cfg(test)]
 rakrs_testkit::canio_err_read! {
-    test_bad_read_2: Magic => "Magic payload mismatch";
+    test_bad_read_2: Magic => DecodeErrorKind::MagicMismatch;
         0x01, 0xff, 0xff, 0x00,
         0xfe, 0xfe, 0xfe, 0xfe,
         0xfd, 0xfd, 0xfd, 0xfd,
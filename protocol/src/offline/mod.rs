@@ -14,6 +14,8 @@ macro_rules! packets {
 // Required methods on packets:
 // fn read<R: Read>(r: R) -> Result<Self>;
 // fn write<W: Write>(&self, w: W) -> Result<()>;
+// fn size(&self) -> usize;
+// fn read_async<'a, R: AsyncRead>(r: &'a mut R) -> impl Future<Output = Result<Self>>;
 
 packets! [
     incompatible_protocol_version IncompatibleProtocolVersion 0x19;
@@ -25,3 +27,98 @@ packets! [
     unconnected_ping_open_connections UnconnectedPingOpenConnections 0x02;
     unconnected_pong UnconnectedPong 0x1c;
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+
+    use rakrs_io::{AsyncCanIo, CanIo, DecodeErrorKind};
+
+    use crate::Magic;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn reply_2() -> OfflinePacket {
+        let address: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+        OfflinePacket::OpenConnectionReply2(OpenConnectionReply2 {
+            magic: Magic,
+            server_id: 1,
+            client_address: address,
+            mtu_size: 1492,
+            server_security: false,
+        })
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let mut buf = vec![0x01]; // UnconnectedPing
+        buf.extend_from_slice(&[0; 8 + 16]);
+        let err = OfflinePacket::decode(&buf).unwrap_err();
+        assert_eq!(err.kind(), DecodeErrorKind::MagicMismatch);
+        assert_eq!(err.packet(), Some("UnconnectedPing"));
+        assert_eq!(err.offset(), Some(25));
+        assert_eq!(
+            err.to_string(),
+            "Magic payload mismatch in UnconnectedPing at byte 25"
+        );
+
+        let err = OfflinePacket::decode(&[0xff]).unwrap_err();
+        assert_eq!(err.kind(), DecodeErrorKind::UnknownVariant(0xff));
+        assert_eq!(err.packet(), Some("OfflinePacket"));
+    }
+
+    #[test]
+    fn test_async() {
+        block_on(async {
+            let packet = reply_2();
+            let mut buf = vec![];
+            packet.write_async(&mut buf).await.unwrap();
+            assert_eq!(buf, packet.encode_to_vec());
+            let read = OfflinePacket::read_async(&mut &buf[..]).await.unwrap();
+            assert_eq!(read, packet);
+
+            let err = OfflinePacket::read_async(&mut &buf[..10])
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+            let err = OfflinePacket::read_async(&mut &[0xff][..])
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Unexpected enum variant 255 in OfflinePacket"
+            );
+        });
+    }
+
+    #[test]
+    fn test_frame() {
+        block_on(async {
+            let packet = reply_2();
+            let mut buf = vec![];
+            rakrs_io::write_frame(&mut buf, &packet).await.unwrap();
+            rakrs_io::write_frame(&mut buf, &packet).await.unwrap();
+            assert_eq!(&buf[..4], &(packet.size() as u32).to_be_bytes());
+
+            let mut r = &buf[..];
+            for _ in 0..2 {
+                let read: OfflinePacket = rakrs_io::read_frame(&mut r, 64).await.unwrap();
+                assert_eq!(read, packet);
+            }
+            assert!(r.is_empty());
+
+            let err = rakrs_io::read_frame::<OfflinePacket, _>(&mut &buf[..], 8)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        });
+    }
+}
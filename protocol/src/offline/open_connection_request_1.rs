@@ -1,33 +1,23 @@
-use std::io::{Read, Result, Write};
-
 use crate::Magic;
-use rakrs_io::CanIo;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, rakrs_codegen::Packet, PartialEq)]
 pub struct OpenConnectionRequest1 {
     pub magic: Magic,
     pub protocol: u8,
+    #[packet(padding_len)]
     pub mtu_size: usize,
 }
 
-impl CanIo for OpenConnectionRequest1 {
-    fn write<W: Write>(&self, mut w: W) -> Result<()> {
-        self.magic.write(&mut w)?;
-        self.protocol.write(&mut w)?;
-
-        w.write_all(&mut vec![0u8; self.mtu_size])?;
-        Ok(())
-    }
-
-    fn read<R: Read>(mut r: R) -> Result<Self> {
-        let magic = <Magic as CanIo>::read(&mut r)?;
-        let protocol = <u8 as CanIo>::read(&mut r)?;
-        let mtu_size = r.bytes().count();
-
-        Ok(Self {
-            magic,
-            protocol,
-            mtu_size,
-        })
+#[cfg(test)]
+rakrs_testkit::canio_ok! {
+    test_read:
+        0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe,
+        0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+        0x0a,
+        0x00, 0x00, 0x00,
+    = test_write: OpenConnectionRequest1 {
+        magic: Magic,
+        protocol: 10,
+        mtu_size: 3,
     }
 }
@@ -1,2 +1,9 @@
+use crate::Magic;
+
+/// Same as `UnconnectedPing`, but should only be answered if the server has open connection slots.
 #[derive(Clone, Debug, rakrs_codegen::Packet, PartialEq)]
-pub struct UnconnectedPingOpenConnections {}
+pub struct UnconnectedPingOpenConnections {
+    pub send_ping_time: u64,
+    pub magic: Magic,
+    pub client_id: u64,
+}
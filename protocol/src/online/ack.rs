@@ -1,8 +1,11 @@
-use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::io::{Read, Result, Write};
 use std::iter::Iterator;
+use std::ops::RangeInclusive;
 
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 
+use rakrs_io::{AsyncCanIo, AsyncRead, DecodeError, DecodeErrorKind, Little, Triad};
+
 use super::CanIo;
 
 const RECORD_TYPE_RANGE: u8 = 0;
@@ -71,7 +74,7 @@ mod cluster_test {
     );
 }
 
-fn write_cluster<W: Write>(cluster: Cluster, mut w: W) -> Result<()> {
+fn write_cluster<W: Write>(cluster: &Cluster, mut w: W) -> Result<()> {
     if cluster.0 == cluster.1 {
         w.write_u8(RECORD_TYPE_SINGLE)?;
         w.write_u24::<LittleEndian>(cluster.0)?;
@@ -94,50 +97,92 @@ fn read_cluster<R: Read>(mut r: R) -> Result<Cluster> {
             r.read_u24::<LittleEndian>()?,
             r.read_u24::<LittleEndian>()?,
         )),
-        _ => Err(Error::new(
-            ErrorKind::Other,
-            format!("Unexpected record type {:?}", ty),
-        )),
+        _ => Err(DecodeError::new(DecodeErrorKind::UnknownRecordType(ty)).into()),
     }
 }
 
-fn encode<W: Write, I>(packets: I, mut w: W) -> Result<()>
-where
-    I: Iterator<Item = PacketNum>,
-{
-    let history = cluster(packets);
+async fn read_cluster_async<R: AsyncRead + Unpin + Send>(r: &mut R) -> Result<Cluster> {
+    async fn read_id<R: AsyncRead + Unpin + Send>(r: &mut R) -> Result<PacketNum> {
+        Ok(Little::<Triad>::read_async(r).await?.inner().into())
+    }
+
+    let ty = u8::read_async(r).await?;
+    match ty {
+        RECORD_TYPE_SINGLE => {
+            let id = read_id(r).await?;
+            Ok(Cluster(id, id))
+        }
+        RECORD_TYPE_RANGE => Ok(Cluster(read_id(r).await?, read_id(r).await?)),
+        _ => Err(DecodeError::new(DecodeErrorKind::UnknownRecordType(ty)).into()),
+    }
+}
 
-    w.write_u16::<BigEndian>(history.len() as u16)?;
-    for cluster in history {
+fn encode<W: Write>(clusters: &[Cluster], mut w: W) -> Result<()> {
+    w.write_u16::<BigEndian>(clusters.len() as u16)?;
+    for cluster in clusters {
         write_cluster(cluster, &mut w)?;
     }
 
     Ok(())
 }
 
-fn decode<R: Read>(mut r: R) -> Result<Vec<PacketNum>> {
+fn decode<R: Read>(mut r: R) -> Result<Vec<Cluster>> {
     let len = r.read_u16::<BigEndian>()?;
     let mut vec = Vec::with_capacity(len as usize);
     for _ in 0..len {
-        let cluster = read_cluster(&mut r)?;
-        for i in cluster.0..=cluster.1 {
-            vec.push(i);
-        }
+        vec.push(read_cluster(&mut r)?);
     }
     Ok(vec)
 }
 
+/// The records of an `Ack` or `Nack`.
+///
+/// Records are kept as ranges, since a single record can cover millions of sequence numbers.
 #[derive(Clone, Debug, PartialEq)]
-struct AckNack(Vec<PacketNum>);
+struct AckNack(Vec<Cluster>);
+
+impl AckNack {
+    fn ranges(&self) -> impl Iterator<Item = RangeInclusive<PacketNum>> + '_ {
+        self.0.iter().map(|cluster| cluster.0..=cluster.1)
+    }
+}
 
 impl CanIo for AckNack {
     fn write<W: Write>(&self, w: W) -> Result<()> {
-        encode(self.0.iter().map(|&i| i), w)
+        encode(&self.0, w)
     }
 
     fn read<R: Read>(r: R) -> Result<Self> {
         Ok(Self(decode(r)?))
     }
+
+    fn size(&self) -> usize {
+        2 + self
+            .0
+            .iter()
+            .map(|cluster| {
+                if cluster.0 == cluster.1 {
+                    1 + 3
+                } else {
+                    1 + 3 + 3
+                }
+            })
+            .sum::<usize>()
+    }
+}
+
+impl AsyncCanIo for AckNack {
+    async fn read_async<'a, R>(r: &'a mut R) -> Result<Self>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        let len = u16::read_async(r).await?;
+        let mut vec = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            vec.push(read_cluster_async(r).await?);
+        }
+        Ok(Self(vec))
+    }
 }
 
 /// Acknowledges that datagrams are received
@@ -147,12 +192,12 @@ pub struct Ack(AckNack);
 impl Ack {
     /// Creates an `Ack` packet.
     pub fn new(vec: Vec<PacketNum>) -> Self {
-        Ack(AckNack(vec))
+        Ack(AckNack(cluster(vec.into_iter())))
     }
 
-    /// Retrieves the sequence numbers of datagrams acknowledged in this packet
-    pub fn packets(&self) -> &Vec<PacketNum> {
-        &self.0 .0
+    /// Retrieves the ranges of sequence numbers of datagrams acknowledged in this packet
+    pub fn ranges(&self) -> impl Iterator<Item = RangeInclusive<PacketNum>> + '_ {
+        self.0.ranges()
     }
 }
 
@@ -163,11 +208,31 @@ pub struct Nack(AckNack);
 impl Nack {
     /// Creates a `Nack` packet.
     pub fn new(vec: Vec<PacketNum>) -> Self {
-        Nack(AckNack(vec))
+        Nack(AckNack(cluster(vec.into_iter())))
     }
 
-    /// Retrieves the sequence numbers of datagrams unacknowledged in this packet
-    pub fn packets(&self) -> &Vec<PacketNum> {
-        &self.0 .0
+    /// Retrieves the ranges of sequence numbers of datagrams unacknowledged in this packet
+    pub fn ranges(&self) -> impl Iterator<Item = RangeInclusive<PacketNum>> + '_ {
+        self.0.ranges()
+    }
+}
+
+#[cfg(test)]
+rakrs_testkit::canio_err_read! {
+    test_bad_record_type: Ack => DecodeErrorKind::UnknownRecordType(2);
+        0x00, 0x01, 0x02, 0x00, 0x00, 0x00,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_range() {
+        // a single record covering every sequence number must not be expanded
+        let buf = [0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff];
+        let ack = Ack::decode(&buf).unwrap();
+        assert_eq!(ack.ranges().collect::<Vec<_>>(), vec![0..=0xFF_FFFF]);
+        assert_eq!(ack.encode_to_vec(), buf);
     }
 }
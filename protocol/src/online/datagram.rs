@@ -1,7 +1,12 @@
 use std::io::{Cursor, Read, Result, Write};
 
+use bytes::{Buf, Bytes};
+
 use super::inner::InnerPacket;
-use rakrs_io::{CanIo, Little, Triad};
+use rakrs_io::{CanIo, DecodeError, Little, Triad};
+
+/// Size of the sequence number preceding the inner packets.
+const SEQ_NUMBER_SIZE: usize = 3;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Datagram {
@@ -9,6 +14,34 @@ pub struct Datagram {
     pub seq_number: Triad,
 }
 
+impl Datagram {
+    /// Decodes a datagram, excluding the leading flags byte, from a received buffer.
+    ///
+    /// The payloads of the inner packets share the memory of `buf`.
+    pub fn decode_bytes(mut buf: Bytes) -> Result<Self> {
+        let seq_number = Little::<Triad>::read(Cursor::new(&buf[..]))
+            .map_err(|err| DecodeError::with_packet(err, "Datagram"))?
+            .inner();
+        buf.advance(SEQ_NUMBER_SIZE);
+        Ok(Self {
+            packets: decode_packets(buf)?,
+            seq_number,
+        })
+    }
+}
+
+/// Decodes the inner packets following the sequence number of a datagram.
+fn decode_packets(mut buf: Bytes) -> Result<Vec<InnerPacket>> {
+    let len = buf.len();
+    let mut packets = vec![];
+    while !buf.is_empty() {
+        let packet = InnerPacket::decode_bytes(&mut buf)
+            .map_err(|err| DecodeError::with_offset(err, SEQ_NUMBER_SIZE + len - buf.len()))?;
+        packets.push(packet);
+    }
+    Ok(packets)
+}
+
 impl CanIo for Datagram {
     fn write<W: Write>(&self, mut w: W) -> Result<()> {
         Little(self.seq_number).write(&mut w)?;
@@ -19,21 +52,20 @@ impl CanIo for Datagram {
     }
 
     fn read<R: Read>(mut r: R) -> Result<Self> {
-        let seq_number = Little::<Triad>::read(&mut r)?.inner();
+        let seq_number = Little::<Triad>::read(&mut r)
+            .map_err(|err| DecodeError::with_packet(err, "Datagram"))?
+            .inner();
 
         let mut buf = vec![];
         r.read_to_end(&mut buf)?;
-        drop(r);
-
-        let mut packets = vec![];
-        let mut cursor = Cursor::new(&buf);
-        while (cursor.position() as usize) < buf.len() {
-            packets.push(InnerPacket::read(&mut cursor)?);
-        }
 
         Ok(Self {
-            packets,
+            packets: decode_packets(buf.into())?,
             seq_number,
         })
     }
+
+    fn size(&self) -> usize {
+        Little(self.seq_number).size() + self.packets.iter().map(CanIo::size).sum::<usize>()
+    }
 }
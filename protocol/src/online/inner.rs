@@ -1,6 +1,7 @@
-use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::io::{Cursor, Read, Result, Write};
 
-use rakrs_io::{CanIo, Little, Triad};
+use bytes::{Buf, Bytes};
+use rakrs_io::{CanIo, DecodeError, DecodeErrorKind, Little, Triad};
 
 const BYTE_SIZE: u8 = 8;
 
@@ -12,30 +13,35 @@ const SPLIT_BIT: u8 = 16;
 pub struct InnerPacket {
     pub reliability: InnerPacketReliability,
     pub split: Option<Split>,
-    pub buffer: Vec<u8>,
+    pub buffer: Bytes,
 }
 
 impl InnerPacket {
-    pub fn size(&self) -> usize {
-        let mut size: usize = 3; // u8 + u16
-
-        if self.reliability.reliable().is_some() {
-            size += 3;
-        }
-        if self.reliability.sequenced().is_some() {
-            size += 3;
-        }
-        if self.reliability.sequenced_or_ordered().is_some() {
-            size += 3 + 1;
-        }
+    /// Decodes an inner packet from the front of `buf`, advancing it past the packet.
+    ///
+    /// Unlike `CanIo::read`, the payload is not copied: `buffer` shares the memory of `buf`.
+    pub fn decode_bytes(buf: &mut Bytes) -> Result<Self> {
+        let annotate = |err, offset| {
+            DecodeError::with_offset(DecodeError::with_packet(err, "InnerPacket"), offset)
+        };
 
-        if self.split.is_some() {
-            size += 4 + 2 + 4;
+        let mut cursor = Cursor::new(&buf[..]);
+        let (reliability, split, payload_bytes) = match read_header(&mut cursor) {
+            Ok(header) => header,
+            Err(err) => return Err(annotate(err, cursor.position() as usize)),
+        };
+        let header_size = cursor.position() as usize;
+        if buf.len() < header_size + payload_bytes {
+            let err = DecodeError::new(DecodeErrorKind::UnexpectedEof);
+            return Err(annotate(err.into(), buf.len()));
         }
+        buf.advance(header_size);
 
-        size += self.buffer.len();
-
-        size
+        Ok(Self {
+            reliability,
+            split,
+            buffer: buf.split_to(payload_bytes),
+        })
     }
 }
 
@@ -86,54 +92,67 @@ impl CanIo for InnerPacket {
     }
 
     fn read<R: Read>(mut r: R) -> Result<Self> {
-        let flags = u8::read(&mut r)?;
-        let has_split = (flags & RELIABILITY_SHIFT) > 0;
-
-        let payload_bits = u16::read(&mut r)?;
-        if payload_bits == 0 {
-            // we have to handle this, otherwise payload_bits - 1 will panick
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Inner packet payload length is zero",
-            ));
-        }
-        let payload_bytes = (payload_bits - 1) / 8 + 1; // ceil_div(payload_bits, 8)
-
-        let reliability = match (flags >> RELIABILITY_SHIFT) & RELIABILITY_MASK {
-            0 => InnerPacketReliability::Unreliable,
-            1 => InnerPacketReliability::UnreliableSequenced(CanIo::read(&mut r)?),
-            2 => InnerPacketReliability::Reliable(CanIo::read(&mut r)?),
-            3 => {
-                InnerPacketReliability::ReliableOrdered(CanIo::read(&mut r)?, CanIo::read(&mut r)?)
-            }
-            4 => InnerPacketReliability::ReliableSequenced(
-                CanIo::read(&mut r)?,
-                CanIo::read(&mut r)?,
-            ),
-            5 => InnerPacketReliability::UnreliableWithAckReceipt,
-            6 => InnerPacketReliability::ReliableWithAckReceipt(CanIo::read(&mut r)?),
-            7 => InnerPacketReliability::ReliableOrderedWithAckReceipt(
-                CanIo::read(&mut r)?,
-                CanIo::read(&mut r)?,
-            ),
-            _ => unreachable!("Already filtered with bitmask"),
-        };
+        let (reliability, split, payload_bytes) =
+            read_header(&mut r).map_err(|err| DecodeError::with_packet(err, "InnerPacket"))?;
 
-        let split = if has_split {
-            Some(Split::read(&mut r)?)
-        } else {
-            None
-        };
-
-        let mut buffer = vec![0u8; payload_bytes as usize];
-        r.read_exact(&mut buffer[..])?;
+        let mut buffer = vec![0u8; payload_bytes];
+        r.read_exact(&mut buffer[..])
+            .map_err(|err| DecodeError::with_packet(err, "InnerPacket"))?;
 
         Ok(Self {
             reliability,
             split,
-            buffer,
+            buffer: buffer.into(),
         })
     }
+
+    fn size(&self) -> usize {
+        let reliability = &self.reliability;
+        1 + 2
+            + reliability.reliable().map_or(0, CanIo::size)
+            + reliability
+                .sequenced()
+                .map_or(0, |sequenced| sequenced.sequence_index.size())
+            + reliability.sequenced_or_ordered().map_or(0, CanIo::size)
+            + self.split.as_ref().map_or(0, CanIo::size)
+            + self.buffer.len()
+    }
+}
+
+/// Reads the fields preceding the payload of an inner packet, along with the payload size.
+fn read_header<R: Read>(mut r: R) -> Result<(InnerPacketReliability, Option<Split>, usize)> {
+    let flags = u8::read(&mut r)?;
+    let has_split = (flags & SPLIT_BIT) > 0;
+
+    let payload_bits = u16::read(&mut r)?;
+    if payload_bits == 0 {
+        // we have to handle this, otherwise payload_bits - 1 will panick
+        return Err(DecodeError::new(DecodeErrorKind::EmptyPayload).into());
+    }
+    let payload_bytes = (payload_bits - 1) / 8 + 1; // ceil_div(payload_bits, 8)
+
+    let reliability = match (flags >> RELIABILITY_SHIFT) & RELIABILITY_MASK {
+        0 => InnerPacketReliability::Unreliable,
+        1 => InnerPacketReliability::UnreliableSequenced(CanIo::read(&mut r)?),
+        2 => InnerPacketReliability::Reliable(CanIo::read(&mut r)?),
+        3 => InnerPacketReliability::ReliableOrdered(CanIo::read(&mut r)?, CanIo::read(&mut r)?),
+        4 => InnerPacketReliability::ReliableSequenced(CanIo::read(&mut r)?, CanIo::read(&mut r)?),
+        5 => InnerPacketReliability::UnreliableWithAckReceipt,
+        6 => InnerPacketReliability::ReliableWithAckReceipt(CanIo::read(&mut r)?),
+        7 => InnerPacketReliability::ReliableOrderedWithAckReceipt(
+            CanIo::read(&mut r)?,
+            CanIo::read(&mut r)?,
+        ),
+        _ => unreachable!("Already filtered with bitmask"),
+    };
+
+    let split = if has_split {
+        Some(Split::read(&mut r)?)
+    } else {
+        None
+    };
+
+    Ok((reliability, split, payload_bytes as usize))
 }
 
 #[repr(u8)]
@@ -243,3 +262,49 @@ pub struct Split {
     pub split_id: u16,
     pub split_index: u32,
 }
+
+#[cfg(test)]
+rakrs_testkit::canio_ok! {
+    test_read_split:
+        0x50, 0x00, 0x10,
+        0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x07, 0x00, 0x00, 0x00, 0x01,
+        0xab, 0xcd,
+    = test_write_split: InnerPacket {
+        reliability: InnerPacketReliability::Reliable(Reliable { message_index: Little(Triad::from(0)) }),
+        split: Some(Split { split_count: 2, split_id: 7, split_index: 1 }),
+        buffer: Bytes::from_static(&[0xab, 0xcd]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_decode_bytes() {
+        let mut buf = Bytes::from_static(&[
+            0x00, 0x00, 0x10, 0xab, 0xcd, //
+            0x40, 0x00, 0x08, 0x01, 0x00, 0x00, 0xef,
+        ]);
+        let payload = buf.slice(3..5);
+
+        let packet = InnerPacket::decode_bytes(&mut buf).unwrap();
+        assert_eq!(packet.reliability, InnerPacketReliability::Unreliable);
+        assert_eq!(packet.buffer, payload);
+        assert_eq!(packet.buffer.as_ptr(), payload.as_ptr());
+
+        let packet = InnerPacket::decode_bytes(&mut buf).unwrap();
+        assert_eq!(packet.buffer, Bytes::from_static(&[0xef]));
+        assert!(buf.is_empty());
+
+        let mut truncated = Bytes::from_static(&[0x00, 0x00, 0x10, 0xab]);
+        let err = InnerPacket::decode_bytes(&mut truncated).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        let err = DecodeError::from(err);
+        assert_eq!(err.kind(), DecodeErrorKind::UnexpectedEof);
+        assert_eq!(err.packet(), Some("InnerPacket"));
+        assert_eq!(err.offset(), Some(4));
+    }
+}
@@ -1,7 +1,8 @@
-use std::io::{Read, Result, Write};
+use std::io::{Cursor, Read, Result, Write};
 
 use bitflags::bitflags;
-use rakrs_io::CanIo;
+use bytes::Bytes;
+use rakrs_io::{CanIo, DecodeError};
 
 mod ack;
 mod datagram;
@@ -68,6 +69,25 @@ impl OnlinePacket {
         }
     }
 
+    /// The exact number of bytes written by `write`.
+    pub fn size(&self) -> usize {
+        let flags_size = 1;
+        flags_size
+            + match self {
+                OnlinePacket::Ack(ack) => ack.size(),
+                OnlinePacket::Nack(nack) => nack.size(),
+                OnlinePacket::Datagram(datagram) => datagram.size(),
+            }
+    }
+
+    /// Encodes the packet into a new buffer of exactly the encoded size.
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size());
+        self.write(&mut buf)
+            .expect("Writing to Vec<u8> never fails");
+        buf
+    }
+
     /// Reads a UDP packet of unknown type and attempts to interpret it as an
     /// `OnlinePacket`.
     pub fn read<R: Read>(mut r: R) -> Result<Option<Self>> {
@@ -85,4 +105,31 @@ impl OnlinePacket {
         };
         Ok(Some(ret))
     }
+
+    /// Interprets a received UDP packet as an `OnlinePacket`, like `read`.
+    ///
+    /// The payloads of datagrams share the memory of `buf` instead of being copied.
+    pub fn decode_bytes(buf: Bytes) -> Result<Option<Self>> {
+        let mut r = Cursor::new(&buf[..]);
+        let flags = Flags::from_bits_truncate(u8::read(&mut r)?);
+        if !flags.contains(Flags::VALID) {
+            return Ok(None);
+        }
+
+        let ret = if flags.contains(Flags::ACK) {
+            OnlinePacket::Ack(read_at(&mut r)?)
+        } else if flags.contains(Flags::NAK) {
+            OnlinePacket::Nack(read_at(&mut r)?)
+        } else {
+            let datagram = Datagram::decode_bytes(buf.slice(1..))
+                .map_err(|err| DecodeError::with_offset(err, 1))?;
+            OnlinePacket::Datagram(datagram)
+        };
+        Ok(Some(ret))
+    }
+}
+
+/// Reads a value from a buffer, recording the offset where decoding failed.
+fn read_at<T: CanIo>(r: &mut Cursor<&[u8]>) -> Result<T> {
+    T::read(&mut *r).map_err(|err| DecodeError::with_offset(err, r.position() as usize))
 }
@@ -0,0 +1,357 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use rakrs_io::CanIo;
+use rakrs_protocol::offline::{self, OfflinePacket};
+use rakrs_protocol::online::OnlinePacket;
+use rakrs_protocol::Magic;
+use tokio::net;
+use tokio::time;
+
+use crate::handshake::{self, MIN_MTU_SIZE, PROTOCOL_VERSION};
+use crate::session::{
+    DisconnectReason, Reliability, Session, SessionConfig, SessionEvent, SessionState,
+};
+
+/// The MTU sizes probed by `Client::connect`, from the largest to the smallest.
+const MTU_SIZES: [u16; 3] = [1492, 1200, 576];
+/// How long to wait for the reply to an offline handshake packet.
+const OFFLINE_TIMEOUT: Duration = Duration::from_millis(500);
+/// The number of `OpenConnectionRequest2` sent before giving up.
+const REQUEST_2_ATTEMPTS: usize = 3;
+/// How long to wait for the online handshake to complete.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest time between two ticks of the session while waiting for packets.
+const TICK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A connection to a RakNet server.
+///
+/// The session is only driven while `send` or `recv` is being awaited, so the application should
+/// keep calling `recv` to acknowledge and resend packets in time.
+pub struct Client {
+    socket: net::UdpSocket,
+    session: Session,
+}
+
+impl Client {
+    /// Connects to the server at `addr`.
+    ///
+    /// The MTU is discovered by sending `OpenConnectionRequest1` with decreasing padding sizes
+    /// until the server replies. Fails with `ErrorKind::TimedOut` if the server does not complete
+    /// the handshake.
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let bind: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let mut socket = net::UdpSocket::bind(bind).await?;
+        let client_id = RandomState::new().build_hasher().finish();
+
+        let mut reply_1 = None;
+        for &mtu in &MTU_SIZES {
+            // a server only replies to probes that reach it, so a timeout suggests that the
+            // datagram was too large for some hop on the path
+            let request = OfflinePacket::OpenConnectionRequest1(offline::OpenConnectionRequest1 {
+                magic: Magic,
+                protocol: PROTOCOL_VERSION,
+                mtu_size: handshake::padding_from_mtu(mtu),
+            });
+            let reply = offline_request(&mut socket, addr, &request, |reply| {
+                matches!(
+                    reply,
+                    OfflinePacket::OpenConnectionReply1(_)
+                        | OfflinePacket::IncompatibleProtocolVersion(_)
+                )
+            })
+            .await?;
+            match reply {
+                Some(OfflinePacket::OpenConnectionReply1(reply)) => {
+                    reply_1 = Some((mtu, reply));
+                    break;
+                }
+                Some(OfflinePacket::IncompatibleProtocolVersion(reply)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!(
+                            "Server requires protocol version {}",
+                            reply.protocol_version
+                        ),
+                    ));
+                }
+                _ => log::debug!("No reply from {} at MTU {}", &addr, mtu),
+            }
+        }
+        let (probed_mtu, reply_1) = reply_1.ok_or_else(|| timed_out("OpenConnectionReply1"))?;
+        // never exceed the size that actually reached the server
+        let mtu = reply_1.mtu_size.min(probed_mtu);
+
+        let request = OfflinePacket::OpenConnectionRequest2(offline::OpenConnectionRequest2 {
+            magic: Magic,
+            server_address: addr,
+            mtu_size: mtu,
+            client_id,
+        });
+        let mut reply_2 = None;
+        for _ in 0..REQUEST_2_ATTEMPTS {
+            let reply = offline_request(&mut socket, addr, &request, |reply| {
+                matches!(reply, OfflinePacket::OpenConnectionReply2(_))
+            })
+            .await?;
+            if let Some(OfflinePacket::OpenConnectionReply2(reply)) = reply {
+                reply_2 = Some(reply);
+                break;
+            }
+        }
+        let reply_2 = reply_2.ok_or_else(|| timed_out("OpenConnectionReply2"))?;
+        if reply_2.mtu_size < MIN_MTU_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Server negotiated an MTU of {} bytes", reply_2.mtu_size),
+            ));
+        }
+        let mtu = reply_2.mtu_size.min(mtu);
+
+        let config = SessionConfig::default();
+        let session = Session::new_client(addr, mtu as usize, client_id, &config, Instant::now());
+        let mut client = Self { socket, session };
+        match time::timeout(CONNECT_TIMEOUT, client.wait_connected()).await {
+            Ok(result) => result?,
+            Err(_) => return Err(timed_out("ConnectionRequestAccepted")),
+        }
+        Ok(client)
+    }
+
+    /// The address of the server.
+    pub fn address(&self) -> &SocketAddr {
+        self.session.address()
+    }
+
+    /// The session with the server.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Sends an application payload to the server.
+    ///
+    /// ACK receipts are not reported by `Client`, so `*WithAckReceipt` reliabilities behave like
+    /// their counterparts without receipts.
+    pub async fn send(
+        &mut self,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+    ) -> io::Result<()> {
+        self.session
+            .send(buffer, reliability, order_channel)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        self.flush().await
+    }
+
+    /// Waits for the next application payload from the server.
+    ///
+    /// Returns `None` when the session is closed.
+    pub async fn recv(&mut self) -> io::Result<Option<Bytes>> {
+        while let Some(event) = self.next_event().await? {
+            match event {
+                SessionEvent::Message(buf) => return Ok(Some(buf)),
+                SessionEvent::Disconnected(_) => return Ok(None),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Closes the session.
+    ///
+    /// Payloads that are already queued are still sent before the server is notified. Waits until
+    /// the server acknowledges them, or a bounded time otherwise.
+    pub async fn close(mut self, reason: DisconnectReason) -> io::Result<()> {
+        if let Err(err) = self.session.close(reason, Instant::now()) {
+            log::debug!("Failed to close {}: {}", self.address(), err);
+            return Ok(());
+        }
+        while self.next_event().await?.is_some() {}
+        self.flush().await // send the final ACKs
+    }
+
+    async fn wait_connected(&mut self) -> io::Result<()> {
+        while let Some(event) = self.next_event().await? {
+            if event == SessionEvent::Connected {
+                return Ok(());
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Server closed the session during the handshake",
+        ))
+    }
+
+    /// Drives the session until it produces an event.
+    ///
+    /// Returns `None` if the session is closed and has no more events.
+    async fn next_event(&mut self) -> io::Result<Option<SessionEvent>> {
+        let mut buf = vec![0; 65536];
+        loop {
+            if let Some(event) = self.session.poll_event() {
+                return Ok(Some(event));
+            }
+            if self.session.state() == SessionState::Disconnected {
+                return Ok(None);
+            }
+            self.flush().await?;
+
+            let (size, remote) =
+                match time::timeout(TICK_INTERVAL, self.socket.recv_from(&mut buf)).await {
+                    Ok(result) => result?,
+                    Err(_) => continue,
+                };
+            if &remote != self.session.address() {
+                continue;
+            }
+            match OnlinePacket::decode_bytes(Bytes::copy_from_slice(&buf[..size])) {
+                Ok(Some(packet)) => self.session.handle(packet, Instant::now()),
+                Ok(None) => log::debug!("Ignored offline packet from {}", &remote),
+                Err(err) => log::error!("Error parsing online packet from {}: {}", &remote, err),
+            }
+        }
+    }
+
+    /// Ticks the session and sends all packets it has ready.
+    async fn flush(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        self.session.tick(now);
+        while let Some(packet) = self.session.poll_send(now) {
+            let buf = packet.encode_to_vec();
+            self.socket
+                .send_to(&buf[..], self.session.address())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends an offline packet to `addr` and waits for a reply accepted by `filter`.
+///
+/// Returns `None` if no such reply arrives within `OFFLINE_TIMEOUT`.
+async fn offline_request(
+    socket: &mut net::UdpSocket,
+    addr: SocketAddr,
+    request: &OfflinePacket,
+    filter: impl Fn(&OfflinePacket) -> bool,
+) -> io::Result<Option<OfflinePacket>> {
+    let buf = request.encode_to_vec();
+    socket.send_to(&buf[..], &addr).await?;
+
+    let wait = async {
+        let mut buf = vec![0; 65536];
+        loop {
+            let (size, remote) = socket.recv_from(&mut buf).await?;
+            if remote != addr {
+                continue;
+            }
+            match OfflinePacket::read(io::Cursor::new(&buf[..size])) {
+                Ok(packet) if filter(&packet) => return Ok(packet),
+                Ok(_) => {}
+                Err(err) => log::debug!("Error parsing offline packet from {}: {}", &addr, err),
+            }
+        }
+    };
+    match time::timeout(OFFLINE_TIMEOUT, wait).await {
+        Ok(result) => result.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+fn timed_out(expected: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("Timed out waiting for {}", expected),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    use crate::session::ReceiptEvent;
+    use crate::{Server, ServerConfig, ServerEvent};
+
+    #[test]
+    fn test_connect() {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let bind: SocketAddr = "127.0.0.1:0".parse().unwrap();
+            let mut server =
+                Server::bind(bind, ServerConfig::default(), |_: &_| String::from("rakrs"))
+                    .await
+                    .unwrap();
+            let addr = server.local_addr();
+            let (sender, mut events) = mpsc::unbounded_channel();
+            tokio::spawn(async move { while sender.send(server.next().await).is_ok() {} });
+
+            let mut client = Client::connect(addr).await.unwrap();
+            assert_eq!(client.session().mtu_size(), 1492);
+            client
+                .send(vec![0xfe, 1, 2], Reliability::ReliableOrdered, 0)
+                .await
+                .unwrap();
+
+            let connection = match events.recv().await {
+                Some(ServerEvent::Connected(connection)) => connection,
+                event => panic!("Unexpected event {:?}", event),
+            };
+            match events.recv().await {
+                Some(ServerEvent::Message(addr, buf)) => {
+                    assert_eq!(&addr, connection.address());
+                    assert_eq!(buf, vec![0xfe, 1, 2]);
+                }
+                event => panic!("Unexpected event {:?}", event),
+            }
+
+            // delivered without waiting for another datagram from the client
+            connection
+                .send(vec![0xfe, 3], Reliability::ReliableOrdered, 0)
+                .await
+                .unwrap();
+            assert_eq!(client.recv().await.unwrap().unwrap(), vec![0xfe, 3]);
+
+            let receipt_id = connection
+                .send(vec![0xfe, 4], Reliability::ReliableOrderedWithAckReceipt, 0)
+                .await
+                .unwrap()
+                .expect("Reliability has an ACK receipt");
+            assert_eq!(client.recv().await.unwrap().unwrap(), vec![0xfe, 4]);
+            // keep the client running until it has acknowledged the payload
+            let _ = time::timeout(Duration::from_millis(100), client.recv()).await;
+            loop {
+                match events.recv().await {
+                    Some(ServerEvent::Receipt(addr, ReceiptEvent::Delivered(id))) => {
+                        assert_eq!(&addr, connection.address());
+                        assert_eq!(id, receipt_id);
+                        break;
+                    }
+                    Some(ServerEvent::PingStats(..)) => {}
+                    event => panic!("Unexpected event {:?}", event),
+                }
+            }
+
+            client.close(DisconnectReason::ClientQuit).await.unwrap();
+            match events.recv().await {
+                Some(ServerEvent::Disconnected(addr, DisconnectReason::ClientQuit)) => {
+                    assert_eq!(&addr, connection.address())
+                }
+                event => panic!("Unexpected event {:?}", event),
+            }
+        });
+    }
+}
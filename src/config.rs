@@ -0,0 +1,253 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use derive_more::Display;
+use getset::{CopyGetters, Getters};
+
+use crate::handshake::{MAX_MTU_SIZE, MIN_MTU_SIZE, PROTOCOL_VERSION};
+use crate::session::{KeepAlive, SessionConfig, SplitLimits, CHANNEL_COUNT};
+
+/// Validated options of a `Server`.
+///
+/// Created with `ServerConfig::builder()`.
+#[derive(Clone, Debug, CopyGetters, Getters)]
+pub struct ServerConfig {
+    /// The ID advertised in offline replies, random by default.
+    #[get_copy = "pub"]
+    server_id: u64,
+    /// The maximum number of open sessions.
+    ///
+    /// `Handshake::respond` ignores `OpenConnectionRequest2` from new peers once this many sessions
+    /// are open. The limit is also advertised to `UnconnectedPingOpenConnections`.
+    #[get_copy = "pub"]
+    max_connections: usize,
+    /// The RakNet protocol versions accepted from clients.
+    #[get = "pub"]
+    protocol_versions: Vec<u8>,
+    /// The smallest MTU accepted from clients.
+    #[get_copy = "pub"]
+    min_mtu: u16,
+    /// The largest MTU accepted from clients; larger probes are reduced to this size.
+    #[get_copy = "pub"]
+    max_mtu: u16,
+    /// Options applied to every session.
+    #[get = "pub"]
+    session: SessionConfig,
+    /// The most verbose log level, applied with `log::set_max_level` when the server starts if
+    /// set. Unset by default, so that the application keeps control of its logging.
+    #[get_copy = "pub"]
+    log_level: Option<log::LevelFilter>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig::builder()
+            .build()
+            .expect("The default configuration is valid")
+    }
+}
+
+impl ServerConfig {
+    /// Starts building a configuration from the defaults.
+    pub fn builder() -> ServerConfigBuilder {
+        ServerConfigBuilder {
+            config: ServerConfig {
+                server_id: RandomState::new().build_hasher().finish(),
+                max_connections: 20,
+                protocol_versions: vec![PROTOCOL_VERSION],
+                min_mtu: MIN_MTU_SIZE,
+                max_mtu: MAX_MTU_SIZE,
+                session: SessionConfig::default(),
+                log_level: None,
+            },
+        }
+    }
+}
+
+/// Builds a `ServerConfig`.
+#[derive(Clone, Debug)]
+pub struct ServerConfigBuilder {
+    config: ServerConfig,
+}
+
+impl ServerConfigBuilder {
+    /// Sets the ID advertised in offline replies.
+    pub fn server_id(mut self, server_id: u64) -> Self {
+        self.config.server_id = server_id;
+        self
+    }
+
+    /// Sets the maximum number of open sessions, beyond which new connections are ignored.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = max_connections;
+        self
+    }
+
+    /// Sets the RakNet protocol versions accepted from clients.
+    ///
+    /// Clients with other versions are told the highest accepted version.
+    pub fn protocol_versions(mut self, versions: &[u8]) -> Self {
+        self.config.protocol_versions = versions.to_vec();
+        self
+    }
+
+    /// Sets the range of MTUs accepted from clients.
+    pub fn mtu_range(mut self, min: u16, max: u16) -> Self {
+        self.config.min_mtu = min;
+        self.config.max_mtu = max;
+        self
+    }
+
+    /// Sets the interval between `ConnectedPing`s sent to each peer.
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.config.session.keep_alive.ping_interval = interval;
+        self
+    }
+
+    /// Sets the time without any packet from a peer after which its session is closed.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.session.keep_alive.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a local close waits for the peer to acknowledge the remaining packets.
+    pub fn close_timeout(mut self, timeout: Duration) -> Self {
+        self.config.session.close_timeout = timeout;
+        self
+    }
+
+    /// Sets the number of order channels available in each session.
+    pub fn channel_count(mut self, channel_count: u8) -> Self {
+        self.config.session.channel_count = channel_count;
+        self
+    }
+
+    /// Sets the bounds on half-finished split packets received from each peer.
+    pub fn split_limits(mut self, limits: SplitLimits) -> Self {
+        self.config.session.split_limits = limits;
+        self
+    }
+
+    /// Sets the most verbose log level.
+    ///
+    /// This is applied to the global logger, so it also affects other crates. Applications that
+    /// only want to filter this crate should filter on the `rakrs` target in their logger instead.
+    pub fn log_level(mut self, level: log::LevelFilter) -> Self {
+        self.config.log_level = Some(level);
+        self
+    }
+
+    /// Validates the options.
+    pub fn build(self) -> Result<ServerConfig, ConfigError> {
+        let config = self.config;
+        if config.max_connections == 0 {
+            return Err(ConfigError::Zero("max_connections"));
+        }
+        if config.protocol_versions.is_empty() {
+            return Err(ConfigError::NoProtocolVersions);
+        }
+        if config.min_mtu < MIN_MTU_SIZE || config.min_mtu > config.max_mtu {
+            return Err(ConfigError::InvalidMtuRange(config.min_mtu, config.max_mtu));
+        }
+
+        let session = &config.session;
+        let KeepAlive {
+            ping_interval,
+            idle_timeout,
+        } = session.keep_alive;
+        if ping_interval == Duration::from_secs(0) {
+            return Err(ConfigError::Zero("ping_interval"));
+        }
+        if ping_interval >= idle_timeout {
+            return Err(ConfigError::PingInterval(ping_interval, idle_timeout));
+        }
+        if session.close_timeout == Duration::from_secs(0) {
+            return Err(ConfigError::Zero("close_timeout"));
+        }
+        if session.channel_count == 0 || session.channel_count > CHANNEL_COUNT {
+            return Err(ConfigError::InvalidChannelCount(session.channel_count));
+        }
+        let limits = &session.split_limits;
+        if limits.max_concurrent == 0 {
+            return Err(ConfigError::Zero("split_limits.max_concurrent"));
+        }
+        if limits.max_count == 0 {
+            return Err(ConfigError::Zero("split_limits.max_count"));
+        }
+        if limits.max_bytes == 0 {
+            return Err(ConfigError::Zero("split_limits.max_bytes"));
+        }
+        if limits.timeout == Duration::from_secs(0) {
+            return Err(ConfigError::Zero("split_limits.timeout"));
+        }
+        Ok(config)
+    }
+}
+
+/// Indicates that a `ServerConfig` is invalid.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum ConfigError {
+    #[display(fmt = "{} must not be zero", _0)]
+    Zero(&'static str),
+    #[display(fmt = "At least one protocol version must be accepted")]
+    NoProtocolVersions,
+    #[display(
+        fmt = "MTU range {}..={} is invalid: the minimum must be between {} and the maximum",
+        _0,
+        _1,
+        MIN_MTU_SIZE
+    )]
+    InvalidMtuRange(u16, u16),
+    #[display(
+        fmt = "Ping interval {:?} must be shorter than the idle timeout {:?}",
+        _0,
+        _1
+    )]
+    PingInterval(Duration, Duration),
+    #[display(fmt = "Channel count {} is not between 1 and {}", _0, CHANNEL_COUNT)]
+    InvalidChannelCount(u8),
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let config = ServerConfig::default();
+        assert_eq!(config.protocol_versions(), &vec![PROTOCOL_VERSION]);
+        assert_eq!(config.min_mtu(), MIN_MTU_SIZE);
+        assert_eq!(config.max_mtu(), MAX_MTU_SIZE);
+        assert_eq!(config.log_level(), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            ServerConfig::builder()
+                .mtu_range(1400, 1200)
+                .build()
+                .unwrap_err(),
+            ConfigError::InvalidMtuRange(1400, 1200)
+        );
+        assert_eq!(
+            ServerConfig::builder()
+                .channel_count(33)
+                .build()
+                .unwrap_err(),
+            ConfigError::InvalidChannelCount(33)
+        );
+        let err = ServerConfig::builder()
+            .ping_interval(Duration::from_secs(10))
+            .idle_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ping interval 10s must be shorter than the idle timeout 5s"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::session::{DisconnectReason, ReceiptId, Reliability};
+
+/// Requests from `Connection` handles, applied to the session by the socket task.
+#[derive(Debug)]
+pub(crate) enum Command {
+    Send {
+        address: SocketAddr,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+        receipt_id: Option<ReceiptId>,
+    },
+    Close {
+        address: SocketAddr,
+        reason: DisconnectReason,
+    },
+}
+
+/// A handle to an established session, through which the application sends payloads to the peer.
+///
+/// Payloads from the peer are reported as `ServerEvent::Message`. This replaces the former
+/// `recv` method, whose per-connection receiver buffered payloads without limit whenever the
+/// application did not drain it. Clones refer to the same session.
+#[derive(Clone)]
+pub struct Connection {
+    address: SocketAddr,
+    channel_count: u8,
+    commands: mpsc::UnboundedSender<Command>,
+    /// The ID of the next message sent with an ACK receipt, shared by all clones.
+    next_receipt_id: Arc<AtomicU32>,
+}
+
+impl Connection {
+    pub(crate) fn new(
+        address: SocketAddr,
+        channel_count: u8,
+        commands: mpsc::UnboundedSender<Command>,
+    ) -> Self {
+        Self {
+            address,
+            channel_count,
+            commands,
+            next_receipt_id: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// The remote address of the peer.
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// Queues an application payload for sending to the peer.
+    ///
+    /// If the reliability has an ACK receipt, returns the ID reported in `ServerEvent::Receipt`
+    /// when the payload is acknowledged or lost.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if `order_channel` is out of bounds for
+    /// `reliability`, or with `ErrorKind::NotConnected` if the server has stopped.
+    pub async fn send(
+        &self,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+    ) -> io::Result<Option<ReceiptId>> {
+        reliability
+            .check_channel(order_channel, self.channel_count)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let receipt_id = if reliability.has_receipt() {
+            Some(ReceiptId(
+                self.next_receipt_id.fetch_add(1, Ordering::Relaxed),
+            ))
+        } else {
+            None
+        };
+        let command = Command::Send {
+            address: self.address,
+            buffer,
+            reliability,
+            order_channel,
+            receipt_id,
+        };
+        self.commands
+            .send(command)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "Server has stopped"))?;
+        Ok(receipt_id)
+    }
+
+    /// Closes the session.
+    ///
+    /// Payloads that are already queued are still sent before the peer is notified.
+    /// `ServerEvent::Disconnected` is reported with `reason` once the peer acknowledges them, or after a
+    /// bounded time otherwise.
+    pub fn close(&self, reason: DisconnectReason) {
+        let command = Command::Close {
+            address: self.address,
+            reason,
+        };
+        let _ = self.commands.send(command); // the session is already gone if the server stopped
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("address", &self.address)
+            .finish()
+    }
+}
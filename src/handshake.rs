@@ -0,0 +1,264 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use rakrs_protocol::offline::{self, OfflinePacket};
+use rakrs_protocol::Magic;
+
+use crate::config::ServerConfig;
+use crate::session::Session;
+
+/// The RakNet protocol version spoken by this crate.
+pub const PROTOCOL_VERSION: u8 = 10;
+
+/// The smallest MTU a server can accept, which is the minimum datagram size every IPv4 host must
+/// accept.
+pub const MIN_MTU_SIZE: u16 = 576;
+
+/// The default largest MTU accepted by a server, which fits in a PPPoE frame.
+pub const MAX_MTU_SIZE: u16 = 1492;
+
+/// Size of the IP and UDP headers, which are counted in the MTU but not in the UDP payload.
+pub(crate) const UDP_HEADER_SIZE: usize = 28;
+
+/// Size of the packet ID, magic and protocol version preceding the padding in
+/// `OpenConnectionRequest1`.
+const REQUEST_1_HEADER_SIZE: usize = 1 + 16 + 1;
+
+/// Answers the offline handshake that precedes a session.
+///
+/// The `Magic` of every offline packet is already validated when the packet is decoded, so only
+/// the protocol version and the MTU need to be checked here. Clients probing an MTU below
+/// `ServerConfig::min_mtu` are ignored, and larger MTUs are reduced to `ServerConfig::max_mtu`.
+#[derive(Clone, Debug, new)]
+pub struct Handshake {
+    config: ServerConfig,
+}
+
+impl Handshake {
+    /// Computes the reply to an offline packet received from `addr`.
+    ///
+    /// `online` is the number of open sessions. `OpenConnectionRequest2` is ignored once it reaches
+    /// `ServerConfig::max_connections`, so that spoofed requests cannot create unlimited sessions.
+    ///
+    /// Returns `None` if the packet is not part of the handshake. The returned session, if any,
+    /// should be stored by the caller, since the peer now considers itself online.
+    pub fn respond(
+        &self,
+        addr: SocketAddr,
+        packet: &OfflinePacket,
+        online: usize,
+        now: Instant,
+    ) -> Option<(OfflinePacket, Option<Session>)> {
+        let ret = match packet {
+            OfflinePacket::OpenConnectionRequest1(request) => {
+                let versions = self.config.protocol_versions();
+                if !versions.contains(&request.protocol) {
+                    let reply = offline::IncompatibleProtocolVersion {
+                        protocol_version: *versions.iter().max().expect("Validated as non-empty"),
+                        magic: Magic,
+                        server_id: self.config.server_id(),
+                    };
+                    return Some((OfflinePacket::IncompatibleProtocolVersion(reply), None));
+                }
+
+                let mtu = mtu_from_padding(request.mtu_size);
+                if mtu < self.config.min_mtu() {
+                    log::debug!("Ignored MTU probe of {} bytes from {}", mtu, &addr);
+                    return None;
+                }
+                let reply = offline::OpenConnectionReply1 {
+                    magic: Magic,
+                    server_id: self.config.server_id(),
+                    server_security: false,
+                    mtu_size: mtu.min(self.config.max_mtu()),
+                };
+                (OfflinePacket::OpenConnectionReply1(reply), None)
+            }
+            OfflinePacket::OpenConnectionRequest2(request) => {
+                if online >= self.config.max_connections() {
+                    log::debug!("Ignored connection from {}: server is full", &addr);
+                    return None;
+                }
+                if request.mtu_size < self.config.min_mtu() {
+                    log::debug!("Rejected MTU of {} bytes from {}", request.mtu_size, &addr);
+                    return None;
+                }
+                let mtu = request.mtu_size.min(self.config.max_mtu());
+                let session = Session::new(addr, mtu as usize, self.config.session(), now);
+                let reply = offline::OpenConnectionReply2 {
+                    magic: Magic,
+                    server_id: self.config.server_id(),
+                    client_address: addr,
+                    mtu_size: mtu,
+                    server_security: false,
+                };
+                (OfflinePacket::OpenConnectionReply2(reply), Some(session))
+            }
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+/// Computes the MTU probed by an `OpenConnectionRequest1` with `padding` bytes of padding.
+fn mtu_from_padding(padding: usize) -> u16 {
+    let mtu = padding + REQUEST_1_HEADER_SIZE + UDP_HEADER_SIZE;
+    mtu.min(u16::MAX as usize) as u16
+}
+
+/// Computes the padding of an `OpenConnectionRequest1` that probes `mtu`.
+pub(crate) fn padding_from_mtu(mtu: u16) -> usize {
+    (mtu as usize).saturating_sub(REQUEST_1_HEADER_SIZE + UDP_HEADER_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake() -> Handshake {
+        let config = ServerConfig::builder().server_id(0x1234).build().unwrap();
+        Handshake::new(config)
+    }
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:19132".parse().unwrap()
+    }
+
+    #[test]
+    fn test_request_1() {
+        let request = OfflinePacket::OpenConnectionRequest1(offline::OpenConnectionRequest1 {
+            magic: Magic,
+            protocol: PROTOCOL_VERSION,
+            mtu_size: 1446,
+        });
+        let (reply, session) = handshake()
+            .respond(addr(), &request, 0, Instant::now())
+            .unwrap();
+        assert_eq!(
+            reply,
+            OfflinePacket::OpenConnectionReply1(offline::OpenConnectionReply1 {
+                magic: Magic,
+                server_id: 0x1234,
+                server_security: false,
+                mtu_size: 1492,
+            })
+        );
+        assert!(session.is_none());
+    }
+
+    #[test]
+    fn test_request_1_bad_protocol() {
+        let request = OfflinePacket::OpenConnectionRequest1(offline::OpenConnectionRequest1 {
+            magic: Magic,
+            protocol: PROTOCOL_VERSION - 1,
+            mtu_size: 1446,
+        });
+        let (reply, session) = handshake()
+            .respond(addr(), &request, 0, Instant::now())
+            .unwrap();
+        assert_eq!(
+            reply,
+            OfflinePacket::IncompatibleProtocolVersion(offline::IncompatibleProtocolVersion {
+                protocol_version: PROTOCOL_VERSION,
+                magic: Magic,
+                server_id: 0x1234,
+            })
+        );
+        assert!(session.is_none());
+    }
+
+    #[test]
+    fn test_request_2() {
+        let request = OfflinePacket::OpenConnectionRequest2(offline::OpenConnectionRequest2 {
+            magic: Magic,
+            server_address: "127.0.0.1:19133".parse().unwrap(),
+            mtu_size: 1492,
+            client_id: 0x5678,
+        });
+        let (reply, session) = handshake()
+            .respond(addr(), &request, 0, Instant::now())
+            .unwrap();
+        assert_eq!(
+            reply,
+            OfflinePacket::OpenConnectionReply2(offline::OpenConnectionReply2 {
+                magic: Magic,
+                server_id: 0x1234,
+                client_address: addr(),
+                mtu_size: 1492,
+                server_security: false,
+            })
+        );
+        assert_eq!(session.unwrap().address(), &addr());
+    }
+
+    #[test]
+    fn test_request_2_full() {
+        let config = ServerConfig::builder().max_connections(1).build().unwrap();
+        let handshake = Handshake::new(config);
+        let request = OfflinePacket::OpenConnectionRequest2(offline::OpenConnectionRequest2 {
+            magic: Magic,
+            server_address: "127.0.0.1:19133".parse().unwrap(),
+            mtu_size: 1492,
+            client_id: 0x5678,
+        });
+        assert!(handshake
+            .respond(addr(), &request, 0, Instant::now())
+            .is_some());
+        assert!(handshake
+            .respond(addr(), &request, 1, Instant::now())
+            .is_none());
+    }
+
+    #[test]
+    fn test_mtu_range() {
+        let config = ServerConfig::builder()
+            .server_id(0x1234)
+            .mtu_range(1000, 1200)
+            .build()
+            .unwrap();
+        let handshake = Handshake::new(config);
+
+        let request = |mtu| {
+            OfflinePacket::OpenConnectionRequest1(offline::OpenConnectionRequest1 {
+                magic: Magic,
+                protocol: PROTOCOL_VERSION,
+                mtu_size: padding_from_mtu(mtu),
+            })
+        };
+        assert!(handshake
+            .respond(addr(), &request(900), 0, Instant::now())
+            .is_none());
+        match handshake.respond(addr(), &request(1492), 0, Instant::now()) {
+            Some((OfflinePacket::OpenConnectionReply1(reply), None)) => {
+                assert_eq!(reply.mtu_size, 1200)
+            }
+            reply => panic!("Unexpected reply {:?}", reply.map(|(reply, _)| reply)),
+        }
+
+        let request = OfflinePacket::OpenConnectionRequest2(offline::OpenConnectionRequest2 {
+            magic: Magic,
+            server_address: "127.0.0.1:19133".parse().unwrap(),
+            mtu_size: 1492,
+            client_id: 0x5678,
+        });
+        match handshake.respond(addr(), &request, 0, Instant::now()) {
+            Some((OfflinePacket::OpenConnectionReply2(reply), Some(session))) => {
+                assert_eq!(reply.mtu_size, 1200);
+                assert_eq!(session.mtu_size(), 1200);
+            }
+            reply => panic!("Unexpected reply {:?}", reply.map(|(reply, _)| reply)),
+        }
+    }
+
+    #[test]
+    fn test_unrelated() {
+        let request = OfflinePacket::UnconnectedPing(offline::UnconnectedPing {
+            send_ping_time: 0,
+            magic: Magic,
+            client_id: 0x5678,
+        });
+        assert!(handshake()
+            .respond(addr(), &request, 0, Instant::now())
+            .is_none());
+    }
+}
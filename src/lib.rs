@@ -1,15 +1,24 @@
-#![allow(dead_code)]
+//! A RakNet implementation on tokio.
+//!
+//! A server is started with `Server::bind` and driven by polling it as a `Stream` of
+//! `ServerEvent`s. Payloads are sent through the `Connection` handles it yields. `Server` replaces
+//! the former `run` function, which returned a driver future and a separate event receiver, so
+//! that the whole server is one value that can be stored in a struct.
 
-use std::io;
+#![allow(dead_code)]
 
-use tokio::net;
+pub use config::ServerConfig;
+pub use connection::Connection;
+pub use server::{Server, ServerEvent};
 
+pub mod client;
+pub mod config;
+mod connection;
+pub mod handshake;
+pub mod ping;
 pub mod server;
 pub mod session;
+mod table;
 
-pub fn run<A>(_bind: A) -> io::Result<()>
-where
-    A: net::ToSocketAddrs,
-{
-    unimplemented!()
-}
+#[macro_use]
+extern crate derive_new;
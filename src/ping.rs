@@ -0,0 +1,132 @@
+use rakrs_protocol::offline::{self, OfflinePacket};
+use rakrs_protocol::Magic;
+
+/// Live information about the server passed to a `MotdProvider`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerStatus {
+    /// The number of established sessions.
+    pub online: usize,
+    /// The maximum number of sessions the server accepts.
+    pub max_connections: usize,
+}
+
+impl ServerStatus {
+    /// Checks whether the server can accept another session.
+    pub fn has_free_slots(&self) -> bool {
+        self.online < self.max_connections
+    }
+}
+
+/// Provides the `server_name` advertised in `UnconnectedPong`.
+///
+/// For Minecraft: Bedrock Edition, this is the `MCPE;motd;protocol;version;players;max;...`
+/// string shown in the server list.
+pub trait MotdProvider: Send {
+    fn server_name(&self, status: &ServerStatus) -> String;
+}
+
+impl<F> MotdProvider for F
+where
+    F: Fn(&ServerStatus) -> String + Send,
+{
+    fn server_name(&self, status: &ServerStatus) -> String {
+        self(status)
+    }
+}
+
+/// Answers `UnconnectedPing` and `UnconnectedPingOpenConnections` with `UnconnectedPong`.
+pub struct PingResponder {
+    server_id: u64,
+    motd: Box<dyn MotdProvider>,
+}
+
+impl PingResponder {
+    pub fn new(server_id: u64, motd: impl MotdProvider + 'static) -> Self {
+        Self {
+            server_id,
+            motd: Box::new(motd),
+        }
+    }
+
+    /// Computes the reply to an offline packet.
+    ///
+    /// Returns `None` if the packet is not a ping, or if it is an `UnconnectedPingOpenConnections`
+    /// and the server is full.
+    pub fn respond(&self, packet: &OfflinePacket, status: &ServerStatus) -> Option<OfflinePacket> {
+        let send_ping_time = match packet {
+            OfflinePacket::UnconnectedPing(ping) => ping.send_ping_time,
+            OfflinePacket::UnconnectedPingOpenConnections(ping) => {
+                if !status.has_free_slots() {
+                    return None;
+                }
+                ping.send_ping_time
+            }
+            _ => return None,
+        };
+
+        let pong = offline::UnconnectedPong {
+            send_ping_time,
+            server_id: self.server_id,
+            magic: Magic,
+            server_name: self.motd.server_name(status),
+        };
+        Some(OfflinePacket::UnconnectedPong(pong))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responder() -> PingResponder {
+        PingResponder::new(0x1234, |status: &ServerStatus| {
+            format!(
+                "MCPE;rakrs;390;1.14.60;{};{}",
+                status.online, status.max_connections
+            )
+        })
+    }
+
+    #[test]
+    fn test_ping() {
+        let ping = OfflinePacket::UnconnectedPing(offline::UnconnectedPing {
+            send_ping_time: 42,
+            magic: Magic,
+            client_id: 0x5678,
+        });
+        let status = ServerStatus {
+            online: 3,
+            max_connections: 3,
+        };
+        assert_eq!(
+            responder().respond(&ping, &status),
+            Some(OfflinePacket::UnconnectedPong(offline::UnconnectedPong {
+                send_ping_time: 42,
+                server_id: 0x1234,
+                magic: Magic,
+                server_name: "MCPE;rakrs;390;1.14.60;3;3".into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_ping_open_connections() {
+        let ping = OfflinePacket::UnconnectedPingOpenConnections(
+            offline::UnconnectedPingOpenConnections {
+                send_ping_time: 42,
+                magic: Magic,
+                client_id: 0x5678,
+            },
+        );
+        let free = ServerStatus {
+            online: 2,
+            max_connections: 3,
+        };
+        assert!(responder().respond(&ping, &free).is_some());
+        let full = ServerStatus {
+            online: 3,
+            max_connections: 3,
+        };
+        assert!(responder().respond(&ping, &full).is_none());
+    }
+}
@@ -1,60 +1,161 @@
-use std::future::Future;
+use std::future;
 use std::io;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
 use rakrs_io::CanIo;
 use rakrs_protocol::{offline, online};
-use tokio::net;
-
-pub async fn run<A, FPollR, FCkR, FOnR, FOffR>(
-    bind: A,
-    poll_send: impl Fn() -> FPollR,
-    query_online: impl Fn(&'_ SocketAddr) -> FCkR,
-    push_online: impl Fn(SocketAddr, online::OnlinePacket) -> FOnR,
-    push_offline: impl Fn(SocketAddr, offline::OfflinePacket) -> FOffR,
-) -> io::Result<()>
-where
-    A: net::ToSocketAddrs,
-    FPollR: Future<Output = Option<(SocketAddr, Vec<u8>)>>, // TODO optimize the return type to reduce allocations
-    FCkR: Future<Output = bool>,
-    FOnR: Future<Output = ()>,
-    FOffR: Future<Output = ()>,
-{
-    let mut socket = net::UdpSocket::bind(&bind).await?;
+use tokio::net::{self, udp};
+use tokio::stream::Stream;
+use tokio::sync::mpsc;
+use tokio::time;
 
-    loop {
-        while let Some((addr, buf)) = poll_send().await {
-            match socket.send_to(&buf[..], &addr).await {
-                Ok(size) => {
-                    if size != buf.len() {
-                        log::warn!(
-                            "Failed to write {} bytes to {}: only wrote {} bytes",
-                            buf.len(),
-                            &addr,
-                            size
-                        );
-                    }
+use crate::config::ServerConfig;
+use crate::connection::Connection;
+use crate::handshake::Handshake;
+use crate::ping::{MotdProvider, PingResponder};
+use crate::session::{DisconnectReason, PingStats, ReceiptEvent};
+use crate::table::SessionTable;
+
+/// The interval between ticks of all sessions, which sends ACKs and resends lost packets.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The number of received datagrams buffered for the server. Further datagrams are dropped until
+/// the server catches up.
+const INBOUND_CAPACITY: usize = 4096;
+
+/// A datagram received by the socket task, or an error from either socket task.
+type Inbound = io::Result<(SocketAddr, Bytes)>;
+
+/// Events produced by a `Server` for the application.
+#[derive(Debug)]
+pub enum ServerEvent {
+    /// A peer has completed the handshake. Payloads are sent through the `Connection`.
+    Connected(Connection),
+    /// A peer has sent an application payload.
+    ///
+    /// Payloads from each peer are reassembled and reported in the order requested by the sender.
+    Message(SocketAddr, Bytes),
+    /// A session has been closed by either side.
+    Disconnected(SocketAddr, DisconnectReason),
+    /// A `ConnectedPong` from a peer measured the round-trip time.
+    ///
+    /// The statistics include the smoothed round-trip time, which is steadier than the sample for
+    /// showing the ping of a player.
+    PingStats(SocketAddr, PingStats),
+    /// A message sent to a peer with an ACK receipt was delivered or lost.
+    ///
+    /// The `ReceiptId` is the one returned by `Connection::send`.
+    Receipt(SocketAddr, ReceiptEvent),
+    /// The socket failed to send or receive a datagram. The server keeps running.
+    Error(io::Error),
+}
+
+/// A RakNet server bound to a UDP socket.
+///
+/// The server is a never-ending `Stream` of `ServerEvent`s. The socket is read and written by two
+/// background tasks, but sessions only make progress while the stream is polled, so the
+/// application should poll it in a loop from a single task. Payloads can be sent from anywhere
+/// through `Connection` handles.
+pub struct Server {
+    local_addr: SocketAddr,
+    table: SessionTable,
+    inbound: mpsc::Receiver<Inbound>,
+    outbound: mpsc::UnboundedSender<(SocketAddr, Vec<u8>)>,
+    ticker: time::Interval,
+}
+
+impl Server {
+    /// Binds a server to `addr` and spawns the socket tasks on the current runtime.
+    ///
+    /// Server list queries are answered with the name from `motd`.
+    pub async fn bind<A, M>(addr: A, config: ServerConfig, motd: M) -> io::Result<Self>
+    where
+        A: net::ToSocketAddrs,
+        M: MotdProvider + 'static,
+    {
+        if let Some(level) = config.log_level() {
+            log::set_max_level(level);
+        }
+        let socket = net::UdpSocket::bind(addr).await?;
+        let local_addr = socket.local_addr()?;
+        let (recv_half, send_half) = socket.split();
+        let (inbound_sender, inbound) = mpsc::channel(INBOUND_CAPACITY);
+        let (outbound, outbound_receiver) = mpsc::unbounded_channel();
+        tokio::spawn(send_loop(
+            send_half,
+            outbound_receiver,
+            inbound_sender.clone(),
+        ));
+        tokio::spawn(recv_loop(recv_half, inbound_sender));
+
+        let handshake = Handshake::new(config.clone());
+        let ping = PingResponder::new(config.server_id(), motd);
+        Ok(Self {
+            local_addr,
+            table: SessionTable::new(handshake, ping, config),
+            inbound,
+            outbound,
+            ticker: time::interval(TICK_INTERVAL),
+        })
+    }
+
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Drives the server until it produces an event.
+    pub async fn next(&mut self) -> ServerEvent {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .expect("Server never ends")
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<ServerEvent> {
+        loop {
+            let now = Instant::now();
+            while let Some(pair) = self.table.poll_send(now) {
+                if self.outbound.send(pair).is_err() {
+                    return Poll::Ready(ServerEvent::Error(stopped()));
                 }
-                Err(err) => {
-                    log::error!("Failed to write {} bytes to {}: {}", buf.len(), &addr, err);
+            }
+            if let Some(event) = self.table.poll_event() {
+                return Poll::Ready(event);
+            }
+
+            let mut progress = self.table.poll_commands(cx);
+            let mut tick = false;
+            while self.ticker.poll_tick(cx).is_ready() {
+                tick = true;
+            }
+            match self.inbound.poll_recv(cx) {
+                Poll::Ready(Some(Ok((remote, buf)))) => {
+                    self.handle(remote, buf);
+                    progress = true;
                 }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(ServerEvent::Error(err)),
+                Poll::Ready(None) => return Poll::Ready(ServerEvent::Error(stopped())),
+                Poll::Pending => {}
             }
-        }
 
-        let mut buf = [0; 65536];
-        let (size, remote) = match socket.recv_from(&mut buf).await {
-            Ok(pair) => pair,
-            Err(err) => {
-                log::error!("Error reading socket: {}", err);
-                continue;
+            // sessions are only ticked on the interval, so that ACKs and queued payloads are
+            // batched instead of flushed after every datagram
+            if tick {
+                self.table.tick(now);
+            } else if !progress {
+                return Poll::Pending;
             }
-        };
-        let data = &buf[..size];
+        }
+    }
 
-        let is_online = query_online(&remote).await;
-        if is_online {
-            match online::OnlinePacket::read(io::Cursor::new(data)) {
-                Ok(Some(packet)) => push_online(remote, packet).await,
+    fn handle(&mut self, remote: SocketAddr, data: Bytes) {
+        if self.table.is_online(&remote) {
+            match online::OnlinePacket::decode_bytes(data) {
+                Ok(Some(packet)) => self.table.push_online(remote, packet),
                 Ok(None) => {
                     log::warn!("Received offline packet from connected session {}", &remote);
                 }
@@ -63,8 +164,8 @@ where
                 }
             }
         } else {
-            match offline::OfflinePacket::read(io::Cursor::new(data)) {
-                Ok(packet) => push_offline(remote, packet).await,
+            match offline::OfflinePacket::read(io::Cursor::new(&data[..])) {
+                Ok(packet) => self.table.push_offline(remote, packet),
                 Err(err) => {
                     log::error!("Error parsing offline packet from {}: {}", &remote, err);
                 }
@@ -72,3 +173,68 @@ where
         }
     }
 }
+
+impl Stream for Server {
+    type Item = ServerEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ServerEvent>> {
+        self.get_mut().poll_event(cx).map(Some)
+    }
+}
+
+/// Passes received datagrams to the server until it is dropped.
+///
+/// Datagrams are dropped while the server is not keeping up, so that a flood cannot exhaust
+/// memory.
+async fn recv_loop(mut socket: udp::RecvHalf, mut inbound: mpsc::Sender<Inbound>) {
+    let mut buf = vec![0; 65536];
+    let mut dropped = 0usize;
+    loop {
+        let result = socket
+            .recv_from(&mut buf)
+            .await
+            .map(|(size, remote)| (remote, Bytes::copy_from_slice(&buf[..size])));
+        match inbound.try_send(result) {
+            Ok(()) if dropped > 0 => {
+                log::warn!(
+                    "Dropped {} datagrams while the server was not keeping up",
+                    dropped
+                );
+                dropped = 0;
+            }
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => dropped += 1,
+            Err(mpsc::error::TrySendError::Closed(_)) => return,
+        }
+    }
+}
+
+/// Sends datagrams queued by the server until it is dropped, reporting errors back to it.
+async fn send_loop(
+    mut socket: udp::SendHalf,
+    mut outbound: mpsc::UnboundedReceiver<(SocketAddr, Vec<u8>)>,
+    mut inbound: mpsc::Sender<Inbound>,
+) {
+    while let Some((addr, buf)) = outbound.recv().await {
+        match socket.send_to(&buf[..], &addr).await {
+            Ok(size) if size != buf.len() => {
+                log::warn!(
+                    "Failed to write {} bytes to {}: only wrote {} bytes",
+                    buf.len(),
+                    &addr,
+                    size
+                );
+            }
+            Ok(_) => {}
+            Err(err) => {
+                if inbound.send(Err(err)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Socket task has stopped")
+}
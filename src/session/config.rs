@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use super::{KeepAlive, SplitLimits, CHANNEL_COUNT};
+
+/// Options applied to a session when it is created.
+#[derive(Clone, Debug)]
+pub struct SessionConfig {
+    /// Timing of keepalive pings and the idle timeout.
+    pub keep_alive: KeepAlive,
+    /// How long a local close waits for the peer to acknowledge the remaining packets.
+    pub close_timeout: Duration,
+    /// The number of order channels accepted from and offered to the peer, at most
+    /// `CHANNEL_COUNT`.
+    pub channel_count: u8,
+    /// Bounds on half-finished split packets received from the peer.
+    pub split_limits: SplitLimits,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive: KeepAlive::default(),
+            close_timeout: Duration::from_secs(2),
+            channel_count: CHANNEL_COUNT,
+            split_limits: SplitLimits::default(),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+/// Limits the number of datagrams in flight to avoid flooding slow peers.
+///
+/// A datagram is in flight from the time it is sent until it is acknowledged, reported missing
+/// or timed out.
+pub trait CongestionControl: Send {
+    /// The maximum number of datagrams in flight.
+    fn window(&self) -> usize;
+
+    /// Called when `count` datagrams in flight are acknowledged.
+    fn on_ack(&mut self, count: usize);
+
+    /// Called when the peer reports datagrams in flight as missing.
+    fn on_nack(&mut self);
+
+    /// Called when datagrams in flight are not acknowledged within the retransmission timeout.
+    fn on_timeout(&mut self);
+}
+
+/// The smallest window of `SlidingWindow`, so that a session never stalls completely.
+const MIN_WINDOW: f64 = 1.0;
+/// The window of `SlidingWindow` before any feedback.
+const INITIAL_WINDOW: f64 = 4.0;
+/// The largest window of `SlidingWindow`.
+const MAX_WINDOW: f64 = 1024.0;
+
+/// Slow start followed by additive-increase/multiplicative-decrease, similar to RakNet's
+/// `CCRakNetSlidingWindow`.
+///
+/// The window grows by one datagram per acknowledged datagram until the slow start threshold,
+/// and by one datagram per window afterwards. A `Nack` halves the window; a timeout restarts slow
+/// start from the minimum window.
+#[derive(Clone, Debug)]
+pub struct SlidingWindow {
+    cwnd: f64,
+    ssthresh: f64,
+}
+
+impl Default for SlidingWindow {
+    fn default() -> Self {
+        Self {
+            cwnd: INITIAL_WINDOW,
+            ssthresh: MAX_WINDOW,
+        }
+    }
+}
+
+impl CongestionControl for SlidingWindow {
+    fn window(&self) -> usize {
+        self.cwnd as usize
+    }
+
+    fn on_ack(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.cwnd < self.ssthresh {
+                self.cwnd += 1.0;
+            } else {
+                self.cwnd += 1.0 / self.cwnd;
+            }
+        }
+        self.cwnd = self.cwnd.min(MAX_WINDOW);
+    }
+
+    fn on_nack(&mut self) {
+        self.ssthresh = (self.cwnd / 2.0).max(MIN_WINDOW);
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_timeout(&mut self) {
+        self.ssthresh = (self.cwnd / 2.0).max(MIN_WINDOW);
+        self.cwnd = MIN_WINDOW;
+    }
+}
+
+/// Does not limit datagrams in flight.
+///
+/// This sends datagrams as soon as they are flushed, which is only suitable for benchmarks and
+/// trusted networks.
+#[derive(Clone, Debug, Default)]
+pub struct Unlimited;
+
+impl CongestionControl for Unlimited {
+    fn window(&self) -> usize {
+        usize::MAX
+    }
+
+    fn on_ack(&mut self, _: usize) {}
+
+    fn on_nack(&mut self) {}
+
+    fn on_timeout(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliding_window() {
+        let mut cc = SlidingWindow::default();
+        assert_eq!(cc.window(), 4);
+        cc.on_ack(4);
+        assert_eq!(cc.window(), 8);
+        cc.on_nack();
+        assert_eq!(cc.window(), 4);
+        cc.on_ack(5);
+        assert_eq!(cc.window(), 5);
+        cc.on_timeout();
+        assert_eq!(cc.window(), 1);
+        cc.on_ack(1);
+        assert_eq!(cc.window(), 2);
+    }
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+/// Timing of the pings that keep a session alive and detect dead peers.
+#[derive(Clone, Debug)]
+pub struct KeepAlive {
+    /// The interval between `ConnectedPing`s sent to the peer.
+    pub ping_interval: Duration,
+    /// The time without any packet from the peer after which the session is closed with
+    /// `DisconnectReason::Timeout`.
+    pub idle_timeout: Duration,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(10),
+        }
+    }
+}
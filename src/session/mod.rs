@@ -1,13 +1,560 @@
+use std::collections::VecDeque;
+use std::io;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
+use rakrs_io::CanIo;
+use rakrs_protocol::encap::{
+    ConnectedPing, ConnectedPong, ConnectionRequest, ConnectionRequestAccepted,
+    DisconnectionNotification, EncapPacket, NewIncomingConnection,
+};
+use rakrs_protocol::online::inner::InnerPacket;
+use rakrs_protocol::online::OnlinePacket;
+
+pub use config::SessionConfig;
+pub use congestion::{CongestionControl, SlidingWindow, Unlimited};
+pub use keep_alive::KeepAlive;
+use order::OrderChannels;
+pub use order::OrderError;
+use recv_window::RecvWindow;
+pub use reliability::{Reliability, SendError};
+pub use rtt::{PingStats, RttEstimator};
 use send_queue::SendQueue;
+pub use send_queue::{ReceiptEvent, ReceiptId, CHANNEL_COUNT};
+use split::SplitAssembler;
+pub use split::{SplitError, SplitLimits};
+pub use state::{DisconnectReason, SessionState, StateError, Transition};
 
+mod config;
+mod congestion;
+mod keep_alive;
+mod order;
+mod recv_window;
+mod reliability;
+mod rtt;
 mod send_queue;
+mod serial;
+mod split;
+mod state;
 
 pub struct Session {
     address: SocketAddr,
+    role: Role,
     send_queue: SendQueue,
+    recv_window: RecvWindow,
+    splits: SplitAssembler,
+    channels: OrderChannels,
+    rtt: RttEstimator,
+    /// The reference point of ping timestamps sent by this session.
+    epoch: Instant,
+    keep_alive: KeepAlive,
+    close_timeout: Duration,
+    last_receive: Instant,
+    last_ping: Instant,
     state: SessionState,
+    /// The reason and the deadline of a local close in progress.
+    close: Option<(DisconnectReason, Instant)>,
+    outbox: VecDeque<OnlinePacket>,
+    events: VecDeque<SessionEvent>,
+}
+
+/// The side of the online handshake played by a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    /// Accepts `ConnectionRequest` and waits for `NewIncomingConnection`.
+    Server,
+    /// Sends `ConnectionRequest` and answers `ConnectionRequestAccepted`.
+    Client,
+}
+
+/// Events produced by a session for the application.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    /// The handshake has completed.
+    Connected,
+    /// A payload that is not handled by the RakNet layer was received.
+    Message(Bytes),
+    /// The session is closed, either by the peer or by a local close that has completed.
+    Disconnected(DisconnectReason),
+    /// A message sent with an ACK receipt was delivered or lost.
+    Receipt(ReceiptEvent),
+    /// A `ConnectedPong` measured the round-trip time to the peer.
+    Pong(PingStats),
+}
+
+impl Session {
+    /// Creates a session for a peer that has completed the offline handshake.
+    pub fn new(address: SocketAddr, mtu_size: usize, config: &SessionConfig, now: Instant) -> Self {
+        Self::with_role(address, Role::Server, mtu_size, config, now)
+    }
+
+    /// Creates a session to a server that has completed the offline handshake, and starts the
+    /// online handshake.
+    pub fn new_client(
+        address: SocketAddr,
+        mtu_size: usize,
+        client_id: u64,
+        config: &SessionConfig,
+        now: Instant,
+    ) -> Self {
+        let mut session = Self::with_role(address, Role::Client, mtu_size, config, now);
+        let request = EncapPacket::ConnectionRequest(ConnectionRequest {
+            client_id,
+            send_ping_time: session.millis_since_epoch(now),
+            use_security: false,
+        });
+        session.send_encap(&request, Reliability::Reliable);
+        session
+    }
+
+    fn with_role(
+        address: SocketAddr,
+        role: Role,
+        mtu_size: usize,
+        config: &SessionConfig,
+        now: Instant,
+    ) -> Self {
+        Self {
+            address,
+            role,
+            send_queue: SendQueue::new(mtu_size, config.channel_count),
+            recv_window: RecvWindow::default(),
+            splits: SplitAssembler::new(config.split_limits.clone()),
+            channels: OrderChannels::new(config.channel_count, OrderChannels::DEFAULT_MAX_BYTES),
+            rtt: RttEstimator::default(),
+            epoch: now,
+            keep_alive: config.keep_alive.clone(),
+            close_timeout: config.close_timeout,
+            last_receive: now,
+            last_ping: now,
+            state: SessionState::Connecting,
+            close: None,
+            outbox: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// The remote address of this session.
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// The MTU negotiated in the offline handshake.
+    pub fn mtu_size(&self) -> usize {
+        self.send_queue.mtu_size()
+    }
+
+    /// The current lifecycle stage of this session.
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// The round-trip time statistics of this session.
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// Handles a packet received from the peer.
+    pub fn handle(&mut self, packet: OnlinePacket, now: Instant) {
+        self.last_receive = now;
+        match packet {
+            OnlinePacket::Datagram(datagram) => {
+                if !self.recv_window.accept_datagram(datagram.seq_number.into()) {
+                    log::debug!(
+                        "Dropped duplicate or stale datagram {:?} from {}",
+                        datagram.seq_number,
+                        &self.address
+                    );
+                    return;
+                }
+                for packet in datagram.packets {
+                    self.handle_inner(packet, now);
+                }
+            }
+            OnlinePacket::Ack(ack) => {
+                if let Some(sample) = self.send_queue.on_ack(ack.ranges(), now) {
+                    self.rtt.update(sample);
+                }
+            }
+            OnlinePacket::Nack(nack) => self.send_queue.on_nack(nack.ranges()),
+        }
+    }
+
+    fn handle_inner(&mut self, packet: InnerPacket, now: Instant) {
+        if let Some(reliable) = packet.reliability.reliable() {
+            if !self
+                .recv_window
+                .accept_message(reliable.message_index.inner().into())
+            {
+                return;
+            }
+        }
+
+        let packet = match self.splits.push(packet, now) {
+            Ok(Some(packet)) => packet,
+            Ok(None) => return,
+            Err(err) => {
+                log::warn!("Dropped split packet from {}: {}", &self.address, err);
+                return;
+            }
+        };
+        let packets = match self.channels.push(packet) {
+            Ok(packets) => packets,
+            Err(err) => {
+                log::warn!("Dropped ordered packet from {}: {}", &self.address, err);
+                return;
+            }
+        };
+        for packet in packets {
+            self.handle_payload(packet.buffer, now);
+        }
+    }
+
+    fn handle_payload(&mut self, buffer: Bytes, now: Instant) {
+        match buffer.first() {
+            Some(&id) if EncapPacket::is_encap_id(id) => {
+                match EncapPacket::read(io::Cursor::new(&buffer[..])) {
+                    Ok(packet) => {
+                        if let Err(err) = self.handle_encap(packet, now) {
+                            log::warn!("Invalid packet from {}: {}", &self.address, err);
+                            // fails only if the session is already closing
+                            let _ = self.close(DisconnectReason::ProtocolError, now);
+                        }
+                    }
+                    Err(err) => {
+                        log::error!("Error parsing encap packet from {}: {}", &self.address, err);
+                    }
+                }
+            }
+            Some(_) => {
+                if self.state.is_connected() {
+                    self.events.push_back(SessionEvent::Message(buffer));
+                } else {
+                    log::warn!(
+                        "Received message from unconnected session {}",
+                        &self.address
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn handle_encap(&mut self, packet: EncapPacket, now: Instant) -> Result<(), StateError> {
+        match packet {
+            EncapPacket::ConnectionRequest(_) if self.role == Role::Server => {
+                self.transition(Transition::ConnectionRequest)?;
+                let reply = EncapPacket::ConnectionRequestAccepted(ConnectionRequestAccepted {
+                    address: self.address,
+                });
+                self.send_encap(&reply, Reliability::Reliable);
+            }
+            EncapPacket::NewIncomingConnection(_) if self.role == Role::Server => {
+                self.transition(Transition::NewIncomingConnection)?;
+                self.events.push_back(SessionEvent::Connected);
+            }
+            EncapPacket::ConnectionRequestAccepted(_) if self.role == Role::Client => {
+                self.transition(Transition::ConnectionRequestAccepted)?;
+                let now_millis = self.millis_since_epoch(now);
+                let reply = EncapPacket::NewIncomingConnection(NewIncomingConnection {
+                    address: self.address,
+                    system_addresses: vec![],
+                    send_ping_time: now_millis,
+                    send_pong_time: now_millis,
+                });
+                self.send_encap(&reply, Reliability::Reliable);
+                self.events.push_back(SessionEvent::Connected);
+            }
+            EncapPacket::ConnectedPing(ping) => {
+                let reply = EncapPacket::ConnectedPong(ConnectedPong {
+                    send_ping_time: ping.send_ping_time,
+                    send_pong_time: self.millis_since_epoch(now),
+                });
+                self.send_encap(&reply, Reliability::Unreliable);
+            }
+            EncapPacket::ConnectedPong(pong) => {
+                let now_millis = self.millis_since_epoch(now);
+                if pong.send_ping_time <= now_millis {
+                    let sample = Duration::from_millis(now_millis - pong.send_ping_time);
+                    let stats = self.rtt.update(sample);
+                    self.events.push_back(SessionEvent::Pong(stats));
+                }
+            }
+            EncapPacket::DisconnectionNotification(_) => {
+                self.transition(Transition::DisconnectionNotification)?;
+                let reason = match (self.close.take(), self.role) {
+                    (Some((reason, _)), _) => reason,
+                    (None, Role::Server) => DisconnectReason::ClientQuit,
+                    (None, Role::Client) => DisconnectReason::ServerShutdown,
+                };
+                self.events.push_back(SessionEvent::Disconnected(reason));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Computes the timestamp used in pings sent by this session.
+    fn millis_since_epoch(&self, now: Instant) -> u64 {
+        now.duration_since(self.epoch).as_millis() as u64
+    }
+
+    fn transition(&mut self, transition: Transition) -> Result<(), StateError> {
+        self.state = self.state.transition(transition)?;
+        if self.state == SessionState::Disconnected {
+            self.send_queue.give_up();
+        }
+        Ok(())
+    }
+
+    fn send_encap(&mut self, packet: &EncapPacket, reliability: Reliability) {
+        self.send_queue
+            .push(packet.encode_to_vec(), reliability, 0)
+            .expect("Unordered packets do not use order channels");
+    }
+
+    /// Queues an application payload for sending.
+    ///
+    /// If the reliability has an ACK receipt, returns the ID reported in `SessionEvent::Receipt`
+    /// when the payload is acknowledged or lost.
+    pub fn send(
+        &mut self,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+    ) -> Result<Option<ReceiptId>, SendError> {
+        self.send_queue.push(buffer, reliability, order_channel)
+    }
+
+    /// Queues an application payload for sending, reporting its delivery with a receipt ID chosen
+    /// by the caller.
+    ///
+    /// `receipt_id` is ignored unless the reliability has an ACK receipt. IDs must not collide
+    /// with those returned by `send` for the same session.
+    pub fn send_with_receipt(
+        &mut self,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+        receipt_id: Option<ReceiptId>,
+    ) -> Result<(), SendError> {
+        self.send_queue
+            .push_with_receipt(buffer, reliability, order_channel, receipt_id)
+    }
+
+    /// Closes the session locally.
+    ///
+    /// The peer is notified after all queued payloads. `SessionEvent::Disconnected` is produced
+    /// once the peer acknowledges everything, or after a bounded time otherwise.
+    pub fn close(&mut self, reason: DisconnectReason, now: Instant) -> Result<(), StateError> {
+        self.transition(Transition::Disconnect)?;
+        let notification = EncapPacket::DisconnectionNotification(DisconnectionNotification {});
+        self.send_encap(&notification, Reliability::Reliable);
+        self.close = Some((reason, now + self.close_timeout));
+        Ok(())
+    }
+
+    /// Performs periodic work, acknowledging received datagrams and flushing pending packets.
+    pub fn tick(&mut self, now: Instant) {
+        self.splits.expire(now);
+
+        if matches!(
+            self.state,
+            SessionState::Connecting | SessionState::Connected
+        ) {
+            if now.duration_since(self.last_receive) >= self.keep_alive.idle_timeout {
+                self.transition(Transition::Timeout)
+                    .expect("Connecting and connected sessions can time out");
+                self.events
+                    .push_back(SessionEvent::Disconnected(DisconnectReason::Timeout));
+                return;
+            }
+            if self.state.is_connected()
+                && now.duration_since(self.last_ping) >= self.keep_alive.ping_interval
+            {
+                let ping = EncapPacket::ConnectedPing(ConnectedPing {
+                    send_ping_time: self.millis_since_epoch(now),
+                });
+                self.send_encap(&ping, Reliability::Unreliable);
+                self.last_ping = now;
+            }
+        }
+
+        if let Some(ack) = self.recv_window.take_ack() {
+            self.outbox.push_back(OnlinePacket::Ack(ack));
+        }
+        if let Some(nack) = self.recv_window.take_nack() {
+            self.outbox.push_back(OnlinePacket::Nack(nack));
+        }
+        if self.send_queue.resend_expired(self.rtt.rto(), now) {
+            self.rtt.backoff();
+        }
+        self.send_queue.flush();
+
+        if let Some((reason, deadline)) = self.close {
+            if self.send_queue.is_acknowledged() || now >= deadline {
+                self.close = None;
+                self.transition(Transition::CloseComplete)
+                    .expect("Sessions with a close in progress are disconnecting");
+                self.events.push_back(SessionEvent::Disconnected(reason));
+            }
+        }
+    }
+
+    /// Replaces the congestion controller of this session.
+    pub fn set_congestion_control(&mut self, congestion: Box<dyn CongestionControl>) {
+        self.send_queue.set_congestion_control(congestion);
+    }
+
+    /// Takes the next packet that should be sent to the peer.
+    pub fn poll_send(&mut self, now: Instant) -> Option<OnlinePacket> {
+        self.outbox.pop_front().or_else(|| {
+            self.send_queue
+                .poll_datagram(now)
+                .map(OnlinePacket::Datagram)
+        })
+    }
+
+    /// Takes the next event that should be passed to the application.
+    pub fn poll_event(&mut self) -> Option<SessionEvent> {
+        self.events
+            .pop_front()
+            .or_else(|| self.send_queue.poll_receipt().map(SessionEvent::Receipt))
+    }
 }
 
-pub enum SessionState {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(now: Instant) -> Session {
+        let addr = "127.0.0.1:19132".parse().unwrap();
+        Session::new_client(addr, 1492, 1, &SessionConfig::default(), now)
+    }
+
+    fn server(now: Instant) -> Session {
+        let addr = "127.0.0.1:50000".parse().unwrap();
+        Session::new(addr, 1492, &SessionConfig::default(), now)
+    }
+
+    /// Delivers all packets sent by `from` to `to`.
+    fn deliver(from: &mut Session, to: &mut Session, now: Instant) {
+        from.tick(now);
+        while let Some(packet) = from.poll_send(now) {
+            to.handle(packet, now);
+        }
+    }
+
+    #[test]
+    fn test_handshake() {
+        let now = Instant::now();
+        let mut client = client(now);
+        let mut server = server(now);
+
+        deliver(&mut client, &mut server, now);
+        assert_eq!(server.state(), SessionState::Connecting);
+        deliver(&mut server, &mut client, now);
+        assert_eq!(client.state(), SessionState::Connected);
+        assert_eq!(client.poll_event(), Some(SessionEvent::Connected));
+        deliver(&mut client, &mut server, now);
+        assert_eq!(server.state(), SessionState::Connected);
+        assert_eq!(server.poll_event(), Some(SessionEvent::Connected));
+
+        client
+            .send(vec![0xfe, 1], Reliability::ReliableOrdered, 0)
+            .unwrap();
+        deliver(&mut client, &mut server, now);
+        assert_eq!(
+            server.poll_event(),
+            Some(SessionEvent::Message(Bytes::from_static(&[0xfe, 1])))
+        );
+    }
+
+    #[test]
+    fn test_close() {
+        let now = Instant::now();
+        let mut client = client(now);
+        let mut server = server(now);
+        for _ in 0..2 {
+            deliver(&mut client, &mut server, now);
+            deliver(&mut server, &mut client, now);
+        }
+        assert_eq!(client.poll_event(), Some(SessionEvent::Connected));
+        assert_eq!(server.poll_event(), Some(SessionEvent::Connected));
+
+        server.close(DisconnectReason::Kicked, now).unwrap();
+        deliver(&mut server, &mut client, now);
+        assert_eq!(
+            client.poll_event(),
+            Some(SessionEvent::Disconnected(DisconnectReason::ServerShutdown))
+        );
+        assert_eq!(server.poll_event(), None);
+        deliver(&mut client, &mut server, now);
+        server.tick(now);
+        assert_eq!(
+            server.poll_event(),
+            Some(SessionEvent::Disconnected(DisconnectReason::Kicked))
+        );
+        assert_eq!(server.state(), SessionState::Disconnected);
+    }
+
+    #[test]
+    fn test_close_timeout() {
+        let now = Instant::now();
+        let mut server = server(now);
+        server.close(DisconnectReason::ServerShutdown, now).unwrap();
+        server.tick(now);
+        assert_eq!(server.poll_event(), None);
+        server.tick(now + SessionConfig::default().close_timeout);
+        assert_eq!(
+            server.poll_event(),
+            Some(SessionEvent::Disconnected(DisconnectReason::ServerShutdown))
+        );
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let now = Instant::now();
+        let mut client = client(now);
+        let mut server = server(now);
+        for _ in 0..2 {
+            deliver(&mut client, &mut server, now);
+            deliver(&mut server, &mut client, now);
+        }
+        assert_eq!(client.poll_event(), Some(SessionEvent::Connected));
+        assert_eq!(server.poll_event(), Some(SessionEvent::Connected));
+
+        let later = now + Duration::from_secs(5);
+        deliver(&mut server, &mut client, later);
+        let pong_time = later + Duration::from_millis(100);
+        client.tick(pong_time);
+        let mut pongs = 0;
+        while let Some(packet) = client.poll_send(pong_time) {
+            if let OnlinePacket::Datagram(datagram) = &packet {
+                pongs += datagram
+                    .packets
+                    .iter()
+                    .filter(|inner| inner.buffer[0] == 0x03)
+                    .count();
+            }
+            server.handle(packet, pong_time);
+        }
+        assert_eq!(pongs, 1);
+        match server.poll_event() {
+            Some(SessionEvent::Pong(stats)) => {
+                assert_eq!(stats.sample, Duration::from_millis(100));
+                assert_eq!(Some(stats.srtt), server.rtt().srtt());
+                assert_eq!(stats.rto, server.rtt().rto());
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        server.tick(pong_time + Duration::from_secs(10));
+        assert_eq!(
+            server.poll_event(),
+            Some(SessionEvent::Disconnected(DisconnectReason::Timeout))
+        );
+        assert_eq!(server.state(), SessionState::Disconnected);
+    }
+}
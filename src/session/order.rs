@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use derive_more::Display;
+use rakrs_protocol::online::inner::InnerPacket;
+
+use super::send_queue::CHANNEL_COUNT;
+use super::serial;
+
+/// Indicates that an ordered or sequenced packet was rejected.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum OrderError {
+    #[display(fmt = "Order channel {} is out of bounds", _0)]
+    InvalidChannel(u8),
+    #[display(fmt = "Too many bytes buffered in order channels")]
+    TooManyBytes,
+}
+
+impl std::error::Error for OrderError {}
+
+/// Releases received packets in the order requested by their reliability.
+///
+/// `ReliableOrdered` packets are buffered until all packets with a lower `order_index` in the same
+/// channel are released. `*Sequenced` packets are released immediately, unless a newer packet in
+/// the same channel has already been released.
+pub struct OrderChannels {
+    channels: Vec<Channel>,
+    max_bytes: usize,
+    buffered_bytes: usize,
+}
+
+#[derive(Default)]
+struct Channel {
+    next_order_index: u32,
+    next_sequence_index: u32,
+    pending: BTreeMap<u32, InnerPacket>,
+}
+
+impl Default for OrderChannels {
+    fn default() -> Self {
+        Self::new(CHANNEL_COUNT, Self::DEFAULT_MAX_BYTES)
+    }
+}
+
+impl OrderChannels {
+    /// The default limit on bytes buffered while waiting for missing ordered packets.
+    pub const DEFAULT_MAX_BYTES: usize = 1 << 20;
+
+    pub fn new(channel_count: u8, max_bytes: usize) -> Self {
+        Self {
+            channels: (0..channel_count).map(|_| Channel::default()).collect(),
+            max_bytes,
+            buffered_bytes: 0,
+        }
+    }
+
+    /// Adds a received packet and returns the packets that can be released now, in order.
+    pub fn push(&mut self, packet: InnerPacket) -> Result<Vec<InnerPacket>, OrderError> {
+        if let Some(sequenced) = packet.reliability.sequenced() {
+            let channel = channel(&mut self.channels, sequenced.ordered.order_channel)?;
+            let order_index: u32 = sequenced.ordered.order_index.inner().into();
+            let sequence_index: u32 = sequenced.sequence_index.inner().into();
+            if serial::precedes(sequence_index, channel.next_sequence_index)
+                || serial::precedes(order_index, channel.next_order_index)
+            {
+                return Ok(vec![]); // stale
+            }
+            channel.next_sequence_index = serial::next(sequence_index);
+            return Ok(vec![packet]);
+        }
+
+        let ordered = match packet.reliability.ordered() {
+            Some(ordered) => ordered,
+            None => return Ok(vec![packet]),
+        };
+        let order_index: u32 = ordered.order_index.inner().into();
+        let channel = channel(&mut self.channels, ordered.order_channel)?;
+
+        if serial::precedes(order_index, channel.next_order_index) {
+            return Ok(vec![]); // duplicate
+        }
+        if order_index != channel.next_order_index {
+            if channel.pending.contains_key(&order_index) {
+                return Ok(vec![]); // duplicate
+            }
+            if self.buffered_bytes + packet.buffer.len() > self.max_bytes {
+                return Err(OrderError::TooManyBytes);
+            }
+            self.buffered_bytes += packet.buffer.len();
+            channel.pending.insert(order_index, packet);
+            return Ok(vec![]);
+        }
+
+        let mut released = vec![packet];
+        channel.next_order_index = serial::next(channel.next_order_index);
+        while let Some(packet) = channel.pending.remove(&channel.next_order_index) {
+            self.buffered_bytes -= packet.buffer.len();
+            released.push(packet);
+            channel.next_order_index = serial::next(channel.next_order_index);
+        }
+        // sequenced packets sent before the latest ordered packet were already checked by
+        // order_index, so the sequence can restart from zero
+        channel.next_sequence_index = 0;
+        Ok(released)
+    }
+}
+
+fn channel(channels: &mut [Channel], order_channel: u8) -> Result<&mut Channel, OrderError> {
+    channels
+        .get_mut(order_channel as usize)
+        .ok_or(OrderError::InvalidChannel(order_channel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rakrs_io::{Little, Triad};
+    use rakrs_protocol::online::inner::{InnerPacketReliability, Ordered, Reliable, Sequenced};
+
+    fn ordered(channel: u8, order_index: u32) -> InnerPacket {
+        InnerPacket {
+            reliability: InnerPacketReliability::ReliableOrdered(
+                Reliable {
+                    message_index: Little(Triad::from(0)),
+                },
+                Ordered {
+                    order_index: Little(Triad::from(order_index)),
+                    order_channel: channel,
+                },
+            ),
+            split: None,
+            buffer: vec![order_index as u8].into(),
+        }
+    }
+
+    fn sequenced(channel: u8, order_index: u32, sequence_index: u32) -> InnerPacket {
+        InnerPacket {
+            reliability: InnerPacketReliability::UnreliableSequenced(Sequenced {
+                sequence_index: Little(Triad::from(sequence_index)),
+                ordered: Ordered {
+                    order_index: Little(Triad::from(order_index)),
+                    order_channel: channel,
+                },
+            }),
+            split: None,
+            buffer: vec![sequence_index as u8].into(),
+        }
+    }
+
+    fn buffers(packets: Vec<InnerPacket>) -> Vec<u8> {
+        packets.into_iter().map(|packet| packet.buffer[0]).collect()
+    }
+
+    #[test]
+    fn test_ordered() {
+        let mut channels = OrderChannels::default();
+        assert_eq!(buffers(channels.push(ordered(0, 0)).unwrap()), vec![0]);
+        assert_eq!(buffers(channels.push(ordered(0, 2)).unwrap()), vec![]);
+        assert_eq!(buffers(channels.push(ordered(0, 3)).unwrap()), vec![]);
+        assert_eq!(buffers(channels.push(ordered(1, 0)).unwrap()), vec![0]);
+        assert_eq!(
+            buffers(channels.push(ordered(0, 1)).unwrap()),
+            vec![1, 2, 3]
+        );
+        assert_eq!(buffers(channels.push(ordered(0, 1)).unwrap()), vec![]);
+        assert_eq!(channels.buffered_bytes, 0);
+    }
+
+    #[test]
+    fn test_sequenced() {
+        let mut channels = OrderChannels::default();
+        assert_eq!(buffers(channels.push(sequenced(0, 0, 1)).unwrap()), vec![1]);
+        assert_eq!(buffers(channels.push(sequenced(0, 0, 0)).unwrap()), vec![]);
+        assert_eq!(buffers(channels.push(sequenced(0, 0, 3)).unwrap()), vec![3]);
+        assert_eq!(buffers(channels.push(ordered(0, 0)).unwrap()), vec![0]);
+        assert_eq!(buffers(channels.push(sequenced(0, 0, 4)).unwrap()), vec![]);
+        assert_eq!(buffers(channels.push(sequenced(0, 1, 0)).unwrap()), vec![0]);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut channels = OrderChannels::default();
+        channels.channels[0].next_order_index = 0xFF_FFFE;
+        assert_eq!(buffers(channels.push(ordered(0, 0)).unwrap()), vec![]);
+        assert_eq!(
+            buffers(channels.push(ordered(0, 0xFF_FFFF)).unwrap()),
+            vec![]
+        );
+        assert_eq!(
+            buffers(channels.push(ordered(0, 0xFF_FFFE)).unwrap()),
+            vec![0xfe, 0xff, 0]
+        );
+        assert_eq!(
+            buffers(channels.push(ordered(0, 0xFF_FFFF)).unwrap()),
+            vec![]
+        );
+        assert_eq!(buffers(channels.push(ordered(0, 1)).unwrap()), vec![1]);
+
+        channels.channels[1].next_sequence_index = 0xFF_FFFF;
+        assert_eq!(buffers(channels.push(sequenced(1, 0, 0)).unwrap()), vec![0]);
+        assert_eq!(
+            buffers(channels.push(sequenced(1, 0, 0xFF_FFFF)).unwrap()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut channels = OrderChannels::new(CHANNEL_COUNT, 1);
+        assert_eq!(
+            channels.push(ordered(32, 0)).unwrap_err(),
+            OrderError::InvalidChannel(32)
+        );
+        assert_eq!(buffers(channels.push(ordered(0, 1)).unwrap()), vec![]);
+        assert_eq!(
+            channels.push(ordered(0, 2)).unwrap_err(),
+            OrderError::TooManyBytes
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeSet, HashSet};
+
+use rakrs_protocol::online::{Ack, Nack};
+
+use super::serial;
+
+/// The number of sequence numbers or message indices tracked by a window.
+///
+/// Anything further ahead is dropped, since an honest peer would not have that many packets in
+/// flight.
+const WINDOW_SIZE: u32 = 2048;
+
+/// Tracks received datagrams and reliable messages to detect duplicates and to generate
+/// `Ack`/`Nack` packets.
+///
+/// Lost datagrams are never received again, since their contents are resent under new sequence
+/// numbers, so the datagram window follows the highest sequence number instead of waiting for
+/// gaps to be filled. Reliable messages keep their index when resent, so the message window only
+/// moves past indices that have been received.
+#[derive(Default)]
+pub struct RecvWindow {
+    /// The oldest sequence number that is still accepted.
+    datagram_start: u32,
+    /// The highest sequence number received so far, plus one.
+    next_seq_number: u32,
+    /// Received sequence numbers from `datagram_start` up to `next_seq_number`.
+    datagrams: HashSet<u32>,
+    ack_queue: BTreeSet<u32>,
+    nack_queue: BTreeSet<u32>,
+    messages: Window,
+}
+
+impl RecvWindow {
+    /// Records an incoming datagram.
+    ///
+    /// Returns `false` if the datagram is a duplicate or outside the window and should be dropped.
+    /// Datagrams more than `WINDOW_SIZE` behind the highest sequence number are too old to be
+    /// told apart from duplicates.
+    pub fn accept_datagram(&mut self, seq_number: u32) -> bool {
+        if seq_number >= serial::MODULUS || serial::precedes(seq_number, self.datagram_start) {
+            return false;
+        }
+        if serial::precedes(seq_number, self.next_seq_number) {
+            if !self.datagrams.insert(seq_number) {
+                return false;
+            }
+        } else {
+            if serial::distance(self.next_seq_number, seq_number) >= WINDOW_SIZE {
+                return false;
+            }
+            let mut missing = self.next_seq_number;
+            while missing != seq_number {
+                self.nack_queue.insert(missing);
+                missing = serial::next(missing);
+            }
+            self.datagrams.insert(seq_number);
+            self.next_seq_number = serial::next(seq_number);
+
+            // skipped sequence numbers that fall out of the window are given up on
+            while serial::distance(self.datagram_start, self.next_seq_number) > WINDOW_SIZE {
+                self.datagrams.remove(&self.datagram_start);
+                self.datagram_start = serial::next(self.datagram_start);
+            }
+        }
+
+        self.ack_queue.insert(seq_number);
+        self.nack_queue.remove(&seq_number);
+        true
+    }
+
+    /// Records an incoming reliable message.
+    ///
+    /// Returns `false` if a message with the same index was already received. This happens when
+    /// the peer resends a datagram under a new sequence number after the original arrived late.
+    pub fn accept_message(&mut self, message_index: u32) -> bool {
+        self.messages.insert(message_index)
+    }
+
+    /// Builds the `Ack` for datagrams received since the last call.
+    pub fn take_ack(&mut self) -> Option<Ack> {
+        if self.ack_queue.is_empty() {
+            return None;
+        }
+        let packets = std::mem::take(&mut self.ack_queue).into_iter().collect();
+        Some(Ack::new(packets))
+    }
+
+    /// Builds the `Nack` for datagrams that were skipped and have not arrived since.
+    pub fn take_nack(&mut self) -> Option<Nack> {
+        if self.nack_queue.is_empty() {
+            return None;
+        }
+        let packets = std::mem::take(&mut self.nack_queue).into_iter().collect();
+        Some(Nack::new(packets))
+    }
+}
+
+/// A sliding window over a sequence of 24-bit indices, which wrap to 0 after `0xFF_FFFF`.
+///
+/// The window only moves past an index once it has been received.
+#[derive(Default)]
+struct Window {
+    /// All indices before this value have been received.
+    start: u32,
+    /// Received indices at or above `start`.
+    received: HashSet<u32>,
+}
+
+impl Window {
+    /// Marks `index` as received. Returns `false` if it is a duplicate or too far ahead.
+    fn insert(&mut self, index: u32) -> bool {
+        // indices before `start` are so far ahead in modular arithmetic that they are rejected too
+        if index >= serial::MODULUS || serial::distance(self.start, index) >= WINDOW_SIZE {
+            return false;
+        }
+        if !self.received.insert(index) {
+            return false;
+        }
+
+        while self.received.remove(&self.start) {
+            self.start = serial::next(self.start);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_order() {
+        let mut window = RecvWindow::default();
+        assert!(window.accept_datagram(0));
+        assert!(window.accept_datagram(1));
+        assert!(!window.accept_datagram(1));
+        assert_eq!(window.take_ack(), Some(Ack::new(vec![0, 1])));
+        assert_eq!(window.take_ack(), None);
+        assert_eq!(window.take_nack(), None);
+    }
+
+    #[test]
+    fn test_gap() {
+        let mut window = RecvWindow::default();
+        assert!(window.accept_datagram(0));
+        assert!(window.accept_datagram(3));
+        assert_eq!(window.take_ack(), Some(Ack::new(vec![0, 3])));
+        assert_eq!(window.take_nack(), Some(Nack::new(vec![1, 2])));
+
+        assert!(window.accept_datagram(2));
+        assert!(window.accept_datagram(4));
+        assert!(!window.accept_datagram(3));
+        assert_eq!(window.take_ack(), Some(Ack::new(vec![2, 4])));
+        assert_eq!(window.take_nack(), None);
+    }
+
+    #[test]
+    fn test_out_of_window() {
+        let mut window = RecvWindow::default();
+        assert!(!window.accept_datagram(WINDOW_SIZE));
+        assert_eq!(window.take_ack(), None);
+    }
+
+    #[test]
+    fn test_lost_datagram() {
+        let mut window = RecvWindow::default();
+        assert!(window.accept_datagram(0));
+        for seq_number in 2..WINDOW_SIZE * 3 {
+            assert!(window.accept_datagram(seq_number));
+        }
+        assert_eq!(window.take_nack(), Some(Nack::new(vec![1])));
+        assert!(!window.accept_datagram(1));
+        assert!(!window.accept_datagram(WINDOW_SIZE * 2 - 1));
+        assert!(window.accept_datagram(WINDOW_SIZE * 4 - 1));
+        assert!(!window.accept_datagram(WINDOW_SIZE * 5));
+        assert!(window.datagrams.len() <= WINDOW_SIZE as usize);
+    }
+
+    #[test]
+    fn test_message_duplicate() {
+        let mut window = RecvWindow::default();
+        assert!(window.accept_message(1));
+        assert!(window.accept_message(0));
+        assert!(!window.accept_message(1));
+        assert!(!window.accept_message(0));
+        assert!(window.accept_message(2));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let start = 0xFF_FFFE;
+        let mut window = RecvWindow {
+            datagram_start: start,
+            next_seq_number: start,
+            messages: Window {
+                start,
+                received: HashSet::new(),
+            },
+            ..RecvWindow::default()
+        };
+        assert!(window.accept_datagram(0xFF_FFFE));
+        assert!(window.accept_datagram(0));
+        assert_eq!(window.take_nack(), Some(Nack::new(vec![0xFF_FFFF])));
+        assert!(window.accept_datagram(0xFF_FFFF));
+        assert!(window.accept_datagram(1));
+        assert!(!window.accept_datagram(0));
+        assert!(!window.accept_datagram(0xFF_FFFD));
+        assert_eq!(window.take_nack(), None);
+        assert_eq!(window.next_seq_number, 2);
+
+        assert!(window.accept_message(0xFF_FFFF));
+        assert!(window.accept_message(0));
+        assert!(window.accept_message(0xFF_FFFE));
+        assert!(!window.accept_message(0));
+        assert!(window.accept_message(1));
+    }
+}
@@ -0,0 +1,87 @@
+use derive_more::Display;
+
+/// Selects how a message is delivered.
+///
+/// Each variant corresponds to one of the reliability modes of `InnerPacketReliability`, so
+/// invalid combinations such as unreliable ordered messages cannot be requested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reliability {
+    /// The message may be lost, duplicated or reordered.
+    Unreliable,
+    /// The message may be lost, and is dropped if a newer sequenced message in the same channel
+    /// has arrived.
+    UnreliableSequenced,
+    /// The message is resent until acknowledged, but may be reordered.
+    Reliable,
+    /// The message is resent until acknowledged, and is delivered in order within its channel.
+    ReliableOrdered,
+    /// The message is resent until acknowledged, and is dropped if a newer sequenced message in
+    /// the same channel has arrived.
+    ReliableSequenced,
+    /// Same as `Unreliable`, and reports whether the message was acknowledged.
+    UnreliableWithAckReceipt,
+    /// Same as `Reliable`, and reports when the message is acknowledged.
+    ReliableWithAckReceipt,
+    /// Same as `ReliableOrdered`, and reports when the message is acknowledged.
+    ReliableOrderedWithAckReceipt,
+}
+
+impl Reliability {
+    /// Checks whether the message is resent until acknowledged.
+    pub fn is_reliable(self) -> bool {
+        match self {
+            Self::Reliable
+            | Self::ReliableOrdered
+            | Self::ReliableSequenced
+            | Self::ReliableWithAckReceipt
+            | Self::ReliableOrderedWithAckReceipt => true,
+            Self::Unreliable | Self::UnreliableSequenced | Self::UnreliableWithAckReceipt => false,
+        }
+    }
+
+    /// Checks whether the message uses an order channel.
+    pub fn uses_channel(self) -> bool {
+        match self {
+            Self::UnreliableSequenced
+            | Self::ReliableOrdered
+            | Self::ReliableSequenced
+            | Self::ReliableOrderedWithAckReceipt => true,
+            Self::Unreliable
+            | Self::Reliable
+            | Self::UnreliableWithAckReceipt
+            | Self::ReliableWithAckReceipt => false,
+        }
+    }
+
+    /// Checks whether an ACK receipt is reported for the message.
+    pub fn has_receipt(self) -> bool {
+        match self {
+            Self::UnreliableWithAckReceipt
+            | Self::ReliableWithAckReceipt
+            | Self::ReliableOrderedWithAckReceipt => true,
+            Self::Unreliable
+            | Self::UnreliableSequenced
+            | Self::Reliable
+            | Self::ReliableOrdered
+            | Self::ReliableSequenced => false,
+        }
+    }
+
+    /// Checks whether `order_channel` can be used with this reliability in a session with
+    /// `channel_count` order channels.
+    pub fn check_channel(self, order_channel: u8, channel_count: u8) -> Result<(), SendError> {
+        if self.uses_channel() && order_channel >= channel_count {
+            return Err(SendError::InvalidChannel(order_channel));
+        }
+        Ok(())
+    }
+}
+
+/// Indicates that a message cannot be sent.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum SendError {
+    #[display(fmt = "Order channel {} is out of bounds", _0)]
+    InvalidChannel(u8),
+}
+
+impl std::error::Error for SendError {}
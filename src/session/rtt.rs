@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+/// The retransmission timeout before any round trip has been measured.
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+/// The lower bound of the retransmission timeout, which prevents spurious resends on LAN.
+const MIN_RTO: Duration = Duration::from_millis(200);
+/// The upper bound of the retransmission timeout, including after backoff.
+const MAX_RTO: Duration = Duration::from_secs(10);
+/// The clock granularity term from RFC 6298.
+const GRANULARITY: Duration = Duration::from_millis(10);
+
+/// Estimates the round-trip time of a session and derives the retransmission timeout from it,
+/// following RFC 6298.
+#[derive(Clone, Debug)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rtt_var: Duration,
+    rto: Duration,
+}
+
+/// The round-trip time statistics of a session after a measurement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PingStats {
+    /// The measured round-trip time.
+    pub sample: Duration,
+    /// The smoothed round-trip time, including `sample`.
+    pub srtt: Duration,
+    /// The round-trip time variance.
+    pub rtt_var: Duration,
+    /// The retransmission timeout.
+    pub rto: Duration,
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        Self {
+            srtt: None,
+            rtt_var: Duration::from_secs(0),
+            rto: INITIAL_RTO,
+        }
+    }
+}
+
+impl RttEstimator {
+    /// The smoothed round-trip time, or `None` if no round trip has been measured yet.
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// The round-trip time variance.
+    pub fn rtt_var(&self) -> Duration {
+        self.rtt_var
+    }
+
+    /// The current retransmission timeout.
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// Adds a measured round-trip time and returns the updated statistics.
+    pub fn update(&mut self, sample: Duration) -> PingStats {
+        let srtt = match self.srtt {
+            None => {
+                self.rtt_var = sample / 2;
+                sample
+            }
+            Some(srtt) => {
+                self.rtt_var = (self.rtt_var * 3 + srtt.abs_diff(sample)) / 4;
+                (srtt * 7 + sample) / 8
+            }
+        };
+        self.srtt = Some(srtt);
+        self.rto = (srtt + GRANULARITY.max(self.rtt_var * 4)).clamp(MIN_RTO, MAX_RTO);
+        PingStats {
+            sample,
+            srtt,
+            rtt_var: self.rtt_var,
+            rto: self.rto,
+        }
+    }
+
+    /// Doubles the retransmission timeout after a resend caused by timeout.
+    pub fn backoff(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_RTO);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let mut rtt = RttEstimator::default();
+        assert_eq!(rtt.srtt(), None);
+        assert_eq!(rtt.rto(), INITIAL_RTO);
+
+        rtt.update(Duration::from_millis(100));
+        assert_eq!(rtt.srtt(), Some(Duration::from_millis(100)));
+        assert_eq!(rtt.rtt_var(), Duration::from_millis(50));
+        assert_eq!(rtt.rto(), Duration::from_millis(300));
+
+        let stats = rtt.update(Duration::from_millis(180));
+        assert_eq!(stats.sample, Duration::from_millis(180));
+        assert_eq!(stats.srtt, Duration::from_millis(110));
+        assert_eq!(stats.rto, rtt.rto());
+        assert_eq!(rtt.srtt(), Some(Duration::from_millis(110)));
+        assert_eq!(
+            rtt.rtt_var(),
+            Duration::from_millis(57) + Duration::from_micros(500)
+        );
+        assert_eq!(rtt.rto(), Duration::from_millis(340));
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut rtt = RttEstimator::default();
+        rtt.update(Duration::from_millis(1));
+        assert_eq!(rtt.rto(), MIN_RTO);
+
+        for _ in 0..10 {
+            rtt.backoff();
+        }
+        assert_eq!(rtt.rto(), MAX_RTO);
+    }
+}
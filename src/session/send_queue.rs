@@ -1,147 +1,213 @@
-use rakrs_io::{Little, Triad};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use rakrs_io::{CanIo, Little, Triad};
 use rakrs_protocol::online::inner::{
-    InnerPacket, InnerPacketReliability as Reliability, Ordered, Reliable, Sequenced, Split,
+    InnerPacket, InnerPacketReliability, Ordered, Reliable, Sequenced, Split,
 };
 use rakrs_protocol::online::Datagram;
 
-const CHANNEL_COUNT: usize = 32;
+use super::congestion::{CongestionControl, SlidingWindow};
+use super::reliability::{Reliability, SendError};
+use super::serial;
+use crate::handshake::UDP_HEADER_SIZE;
+
+/// The maximum number of order channels in a session.
+pub const CHANNEL_COUNT: u8 = 32;
 
-#[derive(Default)]
+/// Size of the flags and the sequence number of a `Datagram`.
+const DATAGRAM_HEADER_SIZE: usize = 1 + 3;
+
+#[derive(new)]
 pub struct SendQueue {
+    /// The negotiated MTU, including the IP and UDP headers.
     mtu_size: usize,
+    /// The number of order channels offered to the peer.
+    channel_count: u8,
+    #[new(value = "Some(vec![])")]
     queue: Option<Vec<InnerPacket>>,
+    /// The receipts of the packets in `queue`.
+    #[new(default)]
+    queue_receipts: Vec<Option<ReceiptId>>,
+    #[new(default)]
     est_size: usize,
+    /// The sequence number of the next datagram, which wraps at 24 bits like the one in ACKs.
+    #[new(default)]
     next_seq_number: u32,
-    send_ordered_indices: [u32; CHANNEL_COUNT],
-    send_sequenced_indices: [u32; CHANNEL_COUNT],
+    #[new(value = "vec![0; channel_count as usize]")]
+    send_ordered_indices: Vec<u32>,
+    #[new(value = "vec![0; channel_count as usize]")]
+    send_sequenced_indices: Vec<u32>,
+    #[new(default)]
     message_index: u32,
+    #[new(default)]
     split_id: u16,
+    #[new(default)]
+    outbox: VecDeque<(Datagram, Vec<Option<ReceiptId>>)>,
+    #[new(default)]
+    recovery: BTreeMap<u32, SentDatagram>,
+    #[new(value = "Box::new(SlidingWindow::default())")]
+    congestion: Box<dyn CongestionControl>,
+    #[new(default)]
+    next_receipt_id: u32,
+    /// The number of unacknowledged packets of each pending receipt.
+    #[new(default)]
+    receipts: HashMap<ReceiptId, usize>,
+    #[new(default)]
+    receipt_events: VecDeque<ReceiptEvent>,
 }
 
-pub enum OrderType {
-    Nil,
-    Ordered { order_channel: u8 },
-    Sequenced { order_channel: u8 },
+/// A datagram in flight, kept until it is acknowledged or considered lost.
+///
+/// Only the reliable packets are kept, since unreliable packets are never resent.
+struct SentDatagram {
+    packets: Vec<(InnerPacket, Option<ReceiptId>)>,
+    unreliable_receipts: Vec<ReceiptId>,
+    send_time: Instant,
 }
 
-impl SendQueue {
-    pub fn push(&mut self, buffer: Vec<u8>, reliable: bool, order_type: OrderType, receipt: bool) {
-        // TODO investigate the feasibility of passing in a lazy enum{CanIo, Vec<u8>} so that
+/// Identifies a message pushed with an ACK receipt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReceiptId(pub u32);
+
+/// Reports the outcome of a message pushed with an ACK receipt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiptEvent {
+    /// All datagrams containing the message were acknowledged.
+    Delivered(ReceiptId),
+    /// The message will not be delivered, either because it was unreliable and a datagram
+    /// containing it was lost, or because the session was closed.
+    Lost(ReceiptId),
+}
 
-        let reliable = if reliable {
-            Some(Reliable {
-                message_index: Default::default(),
-            })
+impl SendQueue {
+    /// Queues a message for sending.
+    ///
+    /// `order_channel` is ignored unless the reliability uses a channel. If the reliability has an
+    /// ACK receipt, returns the ID reported in a `ReceiptEvent` when the message is acknowledged
+    /// or lost.
+    pub fn push(
+        &mut self,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+    ) -> Result<Option<ReceiptId>, SendError> {
+        let receipt_id = if reliability.has_receipt() {
+            let id = ReceiptId(self.next_receipt_id);
+            self.next_receipt_id = self.next_receipt_id.wrapping_add(1);
+            Some(id)
         } else {
             None
         };
+        self.push_with_receipt(buffer, reliability, order_channel, receipt_id)?;
+        Ok(receipt_id)
+    }
 
-        enum FatOrderType {
-            Nil,
-            Ordered(Ordered),
-            Sequenced(Sequenced),
-        }
-
-        let fat = match order_type {
-            OrderType::Nil => FatOrderType::Nil,
-            OrderType::Ordered { order_channel } => FatOrderType::Ordered(Ordered {
-                order_index: {
-                    let r = &mut self.send_ordered_indices[order_channel as usize];
-                    let ret = *r;
-                    *r += 1;
-                    Little::from(Triad::from(ret))
-                },
-                order_channel,
-            }),
-            OrderType::Sequenced { order_channel } => FatOrderType::Sequenced(Sequenced {
-                sequence_index: {
-                    let r = &mut self.send_sequenced_indices[order_channel as usize];
-                    let ret = *r;
-                    *r += 1;
-                    Little::from(Triad::from(ret))
-                },
-                ordered: Ordered {
-                    order_index: Little::from(Triad::from(
-                        self.send_ordered_indices[order_channel as usize],
-                    )),
-                    order_channel,
-                },
-            }),
-        };
+    /// Queues a message for sending, reporting its delivery with a receipt ID chosen by the
+    /// caller.
+    ///
+    /// `receipt_id` is ignored unless the reliability has an ACK receipt. The caller must not
+    /// reuse the ID of a message that is still pending.
+    pub fn push_with_receipt(
+        &mut self,
+        buffer: Vec<u8>,
+        reliability: Reliability,
+        order_channel: u8,
+        receipt_id: Option<ReceiptId>,
+    ) -> Result<(), SendError> {
+        // TODO investigate the feasibility of passing in a lazy enum{CanIo, Vec<u8>} so that
+        // packets built by the session are encoded straight into the inner packet buffer
+        // instead of an intermediate Vec
 
-        let reliability = if let Some(reliable) = reliable {
-            match fat {
-                FatOrderType::Nil => {
-                    if receipt {
-                        Reliability::ReliableWithAckReceipt(reliable)
-                    } else {
-                        Reliability::Reliable(reliable)
-                    }
-                }
-                FatOrderType::Ordered(ordered) => {
-                    if receipt {
-                        Reliability::ReliableOrderedWithAckReceipt(reliable, ordered)
-                    } else {
-                        Reliability::ReliableOrdered(reliable, ordered)
-                    }
-                }
-                FatOrderType::Sequenced(sequenced) => {
-                    if receipt {
-                        panic!("Reliable Sequenced packet cannot have ACK receipt")
-                    } else {
-                        Reliability::ReliableSequenced(reliable, sequenced)
-                    }
-                }
+        reliability.check_channel(order_channel, self.channel_count)?;
+
+        let reliable = Reliable {
+            message_index: Default::default(),
+        };
+        let template = match reliability {
+            Reliability::Unreliable => InnerPacketReliability::Unreliable,
+            Reliability::UnreliableSequenced => {
+                InnerPacketReliability::UnreliableSequenced(self.next_sequenced(order_channel))
             }
-        } else {
-            match fat {
-                FatOrderType::Nil => {
-                    if receipt {
-                        Reliability::UnreliableWithAckReceipt
-                    } else {
-                        Reliability::Unreliable
-                    }
-                }
-                FatOrderType::Ordered(_) => panic!("Unreliable packet cannot be ordered"),
-                FatOrderType::Sequenced(sequenced) => {
-                    if receipt {
-                        panic!("Unreliable Sequenced packet cannot have ACK receipt")
-                    } else {
-                        Reliability::UnreliableSequenced(sequenced)
-                    }
-                }
+            Reliability::Reliable => InnerPacketReliability::Reliable(reliable),
+            Reliability::ReliableOrdered => {
+                InnerPacketReliability::ReliableOrdered(reliable, self.next_ordered(order_channel))
+            }
+            Reliability::ReliableSequenced => InnerPacketReliability::ReliableSequenced(
+                reliable,
+                self.next_sequenced(order_channel),
+            ),
+            Reliability::UnreliableWithAckReceipt => {
+                InnerPacketReliability::UnreliableWithAckReceipt
+            }
+            Reliability::ReliableWithAckReceipt => {
+                InnerPacketReliability::ReliableWithAckReceipt(reliable)
+            }
+            Reliability::ReliableOrderedWithAckReceipt => {
+                InnerPacketReliability::ReliableOrderedWithAckReceipt(
+                    reliable,
+                    self.next_ordered(order_channel),
+                )
             }
         };
 
+        let header_size = InnerPacket {
+            reliability: template.clone(),
+            split: None,
+            buffer: Bytes::new(),
+        }
+        .size();
+        let split_header_size = Split {
+            split_count: 0,
+            split_id: 0,
+            split_index: 0,
+        }
+        .size();
+
         let new_reliability = move |queue: &mut Self| {
-            let mut ret = reliability.clone();
+            let mut ret = template.clone();
             if let Some(reliable) = ret.reliable_mut() {
                 reliable.message_index = Little::from(Triad::from(queue.message_index));
-                queue.message_index += 1;
+                queue.message_index = serial::next(queue.message_index);
             }
             ret
         };
 
-        // https://github.com/pmmp/RakLib/blob/497a8e669203d5f8d2f54d01c2c980b8fc290f75/src/server/Session.php#L376-L377
-        let max_size = self.mtu_size - 60;
+        let available = self
+            .mtu_size
+            .saturating_sub(UDP_HEADER_SIZE + DATAGRAM_HEADER_SIZE);
+        // saturate so that a bogus MTU degrades throughput instead of panicking
+        let max_size = available
+            .saturating_sub(header_size + split_header_size)
+            .max(1);
+        let split_count = if header_size + buffer.len() <= available {
+            1
+        } else {
+            buffer.len().div_ceil(max_size).max(1)
+        };
+
+        let receipt_id = receipt_id.filter(|_| reliability.has_receipt());
+        if let Some(id) = receipt_id {
+            self.receipts.insert(id, split_count);
+        }
 
-        if buffer.len() <= max_size {
+        let buffer = Bytes::from(buffer);
+        if split_count == 1 {
             let packet = InnerPacket {
                 reliability: new_reliability(self),
                 split: None,
                 buffer,
             };
-            self.push_inner(packet);
+            self.push_inner(packet, receipt_id);
         } else {
-            // TODO Let's try to prevent allocating O(n/m) vecs and directly write to a Datagram
-
-            let chunks = buffer.chunks(max_size);
-            let split_count = chunks.len();
-
             let split_id = self.split_id;
             self.split_id = split_id.wrapping_add(1);
 
-            for (split_index, chunk) in chunks.enumerate() {
+            for split_index in 0..split_count {
+                let start = split_index * max_size;
+                let end = (start + max_size).min(buffer.len());
                 let packet = InnerPacket {
                     reliability: new_reliability(self),
                     split: Some(Split {
@@ -149,41 +215,434 @@ impl SendQueue {
                         split_id,
                         split_index: split_index as u32,
                     }),
-                    buffer: chunk.to_vec(),
+                    buffer: buffer.slice(start..end),
                 };
-                self.push_inner(packet);
+                self.push_inner(packet, receipt_id);
             }
         }
+
+        Ok(())
+    }
+
+    /// The negotiated MTU.
+    pub fn mtu_size(&self) -> usize {
+        self.mtu_size
+    }
+
+    fn next_ordered(&mut self, order_channel: u8) -> Ordered {
+        let r = &mut self.send_ordered_indices[order_channel as usize];
+        let order_index = *r;
+        *r = serial::next(order_index);
+        // the receiver restarts the sequence whenever an ordered packet is released
+        self.send_sequenced_indices[order_channel as usize] = 0;
+        Ordered {
+            order_index: Little::from(Triad::from(order_index)),
+            order_channel,
+        }
+    }
+
+    fn next_sequenced(&mut self, order_channel: u8) -> Sequenced {
+        let r = &mut self.send_sequenced_indices[order_channel as usize];
+        let sequence_index = *r;
+        *r = serial::next(sequence_index);
+        Sequenced {
+            sequence_index: Little::from(Triad::from(sequence_index)),
+            ordered: Ordered {
+                order_index: Little::from(Triad::from(
+                    self.send_ordered_indices[order_channel as usize],
+                )),
+                order_channel,
+            },
+        }
     }
 
-    fn push_inner(&mut self, packet: InnerPacket) {
+    fn push_inner(&mut self, packet: InnerPacket, receipt: Option<ReceiptId>) {
         let size = packet.size();
         self.flush_if_long(size);
 
         self.queue.as_mut().unwrap().push(packet);
+        self.queue_receipts.push(receipt);
         self.est_size += size;
 
         self.flush_if_long(0);
     }
 
     fn flush_if_long(&mut self, extra: usize) {
-        if self.est_size + 4 + 20 + 8 + 8 + extra > self.mtu_size {
+        if UDP_HEADER_SIZE + DATAGRAM_HEADER_SIZE + self.est_size + extra > self.mtu_size {
             self.flush();
         }
     }
 
-    fn flush(&mut self) {
-        let _datagram = Datagram {
+    /// Packs all queued packets into a datagram ready for dispatch.
+    pub fn flush(&mut self) {
+        if self.queue.as_ref().unwrap().is_empty() {
+            return;
+        }
+
+        let datagram = Datagram {
             seq_number: {
                 let r = self.next_seq_number;
-                self.next_seq_number += 1;
+                self.next_seq_number = serial::next(r);
                 r.into()
             },
             packets: self.queue.replace(vec![]).unwrap(),
         };
+        self.est_size = 0;
+
+        let receipts = std::mem::take(&mut self.queue_receipts);
+        self.outbox.push_back((datagram, receipts));
+    }
+
+    /// Checks whether all pushed packets have been sent and acknowledged.
+    pub fn is_acknowledged(&self) -> bool {
+        self.queue.as_ref().unwrap().is_empty()
+            && self.outbox.is_empty()
+            && self.recovery.is_empty()
+    }
+
+    /// Takes the next datagram that should be sent to the socket, unless the congestion window is
+    /// full.
+    pub fn poll_datagram(&mut self, now: Instant) -> Option<Datagram> {
+        if self.recovery.len() >= self.congestion.window() {
+            return None;
+        }
+        let (datagram, receipts) = self.outbox.pop_front()?;
+
+        let mut sent = SentDatagram {
+            packets: vec![],
+            unreliable_receipts: vec![],
+            send_time: now,
+        };
+        for (packet, receipt) in datagram.packets.iter().zip(receipts) {
+            if packet.reliability.reliable().is_some() {
+                sent.packets.push((packet.clone(), receipt));
+            } else if let Some(receipt) = receipt {
+                sent.unreliable_receipts.push(receipt);
+            }
+        }
+        self.recovery.insert(datagram.seq_number.into(), sent);
+
+        Some(datagram)
+    }
+
+    /// Replaces the congestion controller, e.g. with `Unlimited` for benchmarks.
+    pub fn set_congestion_control(&mut self, congestion: Box<dyn CongestionControl>) {
+        self.congestion = congestion;
+    }
 
-        // TODO handle NACK resending
+    /// Releases the datagrams acknowledged by the peer.
+    ///
+    /// Returns the round-trip time of the most recently sent datagram among them.
+    pub fn on_ack<I>(&mut self, ranges: I, now: Instant) -> Option<Duration>
+    where
+        I: IntoIterator<Item = RangeInclusive<u32>>,
+    {
+        let mut count = 0;
+        let mut last_send_time = None;
+        for seq_number in self.take_in_flight(ranges) {
+            if let Some(sent) = self.recovery.remove(&seq_number) {
+                count += 1;
+                last_send_time = last_send_time.max(Some(sent.send_time));
 
-        unimplemented!("Dispatch datagram")
+                let receipts = sent.packets.iter().filter_map(|(_, receipt)| *receipt);
+                for receipt in receipts.chain(sent.unreliable_receipts) {
+                    self.confirm_receipt(receipt);
+                }
+            }
+        }
+        if count > 0 {
+            self.congestion.on_ack(count);
+        }
+        last_send_time.map(|send_time| now.duration_since(send_time))
+    }
+
+    /// Resends the reliable packets of the datagrams reported missing by the peer.
+    pub fn on_nack<I>(&mut self, ranges: I)
+    where
+        I: IntoIterator<Item = RangeInclusive<u32>>,
+    {
+        let mut any = false;
+        for seq_number in self.take_in_flight(ranges) {
+            if let Some(sent) = self.recovery.remove(&seq_number) {
+                any = true;
+                self.resend(sent);
+            }
+        }
+        if any {
+            self.congestion.on_nack();
+        }
+    }
+
+    /// Lists the sequence numbers of datagrams in flight within `ranges`.
+    ///
+    /// Only the recovery map is walked, since the peer can report ranges of any size.
+    fn take_in_flight<I>(&self, ranges: I) -> Vec<u32>
+    where
+        I: IntoIterator<Item = RangeInclusive<u32>>,
+    {
+        let mut seq_numbers = vec![];
+        for range in ranges {
+            if range.start() <= range.end() {
+                seq_numbers.extend(
+                    self.recovery
+                        .range(range)
+                        .map(|(&seq_number, _)| seq_number),
+                );
+            }
+        }
+        seq_numbers
+    }
+
+    /// Resends the reliable packets of the datagrams that have not been acknowledged within
+    /// `timeout`.
+    ///
+    /// Returns whether any datagram has expired.
+    pub fn resend_expired(&mut self, timeout: Duration, now: Instant) -> bool {
+        let expired: Vec<u32> = self
+            .recovery
+            .iter()
+            .filter(|(_, sent)| now.duration_since(sent.send_time) >= timeout)
+            .map(|(&seq_number, _)| seq_number)
+            .collect();
+        if expired.is_empty() {
+            return false;
+        }
+        for seq_number in expired {
+            let sent = self.recovery.remove(&seq_number).unwrap();
+            self.resend(sent);
+        }
+        self.congestion.on_timeout();
+        true
+    }
+
+    /// Queues the packets of a lost datagram again. The packets keep their message indices, so
+    /// the peer can still drop them if the original datagram arrives late.
+    fn resend(&mut self, sent: SentDatagram) {
+        for (packet, receipt) in sent.packets {
+            self.push_inner(packet, receipt);
+        }
+        for receipt in sent.unreliable_receipts {
+            self.lose_receipt(receipt);
+        }
+    }
+
+    fn confirm_receipt(&mut self, receipt: ReceiptId) {
+        if let Some(remaining) = self.receipts.get_mut(&receipt) {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.receipts.remove(&receipt);
+                self.receipt_events
+                    .push_back(ReceiptEvent::Delivered(receipt));
+            }
+        }
+    }
+
+    fn lose_receipt(&mut self, receipt: ReceiptId) {
+        if self.receipts.remove(&receipt).is_some() {
+            self.receipt_events.push_back(ReceiptEvent::Lost(receipt));
+        }
+    }
+
+    /// Reports all pending receipts as lost. Called when the session is closed.
+    pub fn give_up(&mut self) {
+        let receipts: Vec<ReceiptId> = self.receipts.keys().copied().collect();
+        for receipt in receipts {
+            self.lose_receipt(receipt);
+        }
+    }
+
+    /// Takes the next receipt outcome.
+    pub fn poll_receipt(&mut self) -> Option<ReceiptEvent> {
+        self.receipt_events.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::congestion::Unlimited;
+    use rakrs_protocol::online::OnlinePacket;
+
+    fn seq_number(datagram: &Datagram) -> u32 {
+        datagram.seq_number.into()
+    }
+
+    #[test]
+    fn test_nack_resend() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        queue.push(vec![1], Reliability::Reliable, 0).unwrap();
+        queue.push(vec![2], Reliability::Unreliable, 0).unwrap();
+        queue.flush();
+        let sent = queue.poll_datagram(now).unwrap();
+        assert_eq!(seq_number(&sent), 0);
+        assert_eq!(sent.packets.len(), 2);
+
+        queue.on_nack(vec![0..=0]);
+        queue.flush();
+        let resent = queue.poll_datagram(now).unwrap();
+        assert_eq!(seq_number(&resent), 1);
+        assert_eq!(resent.packets, vec![sent.packets[0].clone()]);
+
+        let later = now + Duration::from_millis(50);
+        assert_eq!(
+            queue.on_ack(vec![1..=1], later),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(queue.on_ack(vec![1..=1], later), None);
+        queue.on_nack(vec![1..=1]);
+        queue.flush();
+        assert!(queue.poll_datagram(now).is_none());
+    }
+
+    #[test]
+    fn test_timeout_resend() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(1);
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        queue.push(vec![1], Reliability::Reliable, 0).unwrap();
+        queue.flush();
+        assert!(queue.poll_datagram(now).is_some());
+
+        assert!(!queue.resend_expired(timeout, now));
+        queue.flush();
+        assert!(queue.poll_datagram(now).is_none());
+
+        let later = now + timeout;
+        assert!(queue.resend_expired(timeout, later));
+        queue.flush();
+        assert_eq!(seq_number(&queue.poll_datagram(later).unwrap()), 1);
+    }
+
+    #[test]
+    fn test_receipts() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        queue.set_congestion_control(Box::new(Unlimited));
+        let reliable = queue
+            .push(vec![0; 2000], Reliability::ReliableWithAckReceipt, 0)
+            .unwrap()
+            .unwrap();
+        queue.flush();
+        let unreliable = queue
+            .push(vec![1], Reliability::UnreliableWithAckReceipt, 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(queue.push(vec![2], Reliability::Unreliable, 0), Ok(None));
+        queue.flush();
+        while queue.poll_datagram(now).is_some() {}
+
+        queue.on_nack(vec![0..=0, 2..=2]);
+        assert_eq!(queue.poll_receipt(), Some(ReceiptEvent::Lost(unreliable)));
+        queue.on_ack(vec![1..=1], now);
+        assert_eq!(queue.poll_receipt(), None);
+        queue.flush();
+        assert_eq!(seq_number(&queue.poll_datagram(now).unwrap()), 3);
+        queue.on_ack(vec![3..=3], now);
+        assert_eq!(
+            queue.poll_receipt(),
+            Some(ReceiptEvent::Delivered(reliable))
+        );
+        assert_eq!(queue.poll_receipt(), None);
+
+        let lost = queue
+            .push(vec![3], Reliability::ReliableWithAckReceipt, 0)
+            .unwrap()
+            .unwrap();
+        queue.give_up();
+        assert_eq!(queue.poll_receipt(), Some(ReceiptEvent::Lost(lost)));
+    }
+
+    #[test]
+    fn test_invalid_channel() {
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        assert_eq!(
+            queue.push(vec![0], Reliability::ReliableOrdered, 32),
+            Err(SendError::InvalidChannel(32))
+        );
+        assert_eq!(queue.push(vec![0], Reliability::Reliable, 32), Ok(None));
+    }
+
+    #[test]
+    fn test_congestion_window() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        for i in 0..5 {
+            queue.push(vec![i], Reliability::Reliable, 0).unwrap();
+            queue.flush();
+        }
+        for _ in 0..4 {
+            assert!(queue.poll_datagram(now).is_some());
+        }
+        assert!(queue.poll_datagram(now).is_none());
+
+        queue.on_ack(vec![0..=0], now);
+        assert_eq!(seq_number(&queue.poll_datagram(now).unwrap()), 4);
+
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        queue.set_congestion_control(Box::new(Unlimited));
+        for i in 0..5 {
+            queue.push(vec![i], Reliability::Reliable, 0).unwrap();
+            queue.flush();
+            assert!(queue.poll_datagram(now).is_some());
+        }
+    }
+
+    #[test]
+    fn test_wrap() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        queue.next_seq_number = 0xFF_FFFF;
+        queue.message_index = 0xFF_FFFF;
+        queue.send_ordered_indices[0] = 0xFF_FFFF;
+        for _ in 0..2 {
+            queue
+                .push(vec![0], Reliability::ReliableOrdered, 0)
+                .unwrap();
+            queue.flush();
+        }
+        let first = queue.poll_datagram(now).unwrap();
+        let second = queue.poll_datagram(now).unwrap();
+        assert_eq!(seq_number(&first), 0xFF_FFFF);
+        assert_eq!(seq_number(&second), 0);
+        let indices = |datagram: &Datagram| {
+            let reliability = &datagram.packets[0].reliability;
+            let message_index: u32 = reliability.reliable().unwrap().message_index.inner().into();
+            let order_index: u32 = reliability.ordered().unwrap().order_index.inner().into();
+            (message_index, order_index)
+        };
+        assert_eq!(indices(&first), (0xFF_FFFF, 0xFF_FFFF));
+        assert_eq!(indices(&second), (0, 0));
+
+        queue.on_ack(vec![0xFF_FFFF..=0xFF_FFFF, 0..=0xFF_FFFE], now);
+        assert!(queue.is_acknowledged());
+    }
+
+    #[test]
+    fn test_mtu_packing() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new(1492, CHANNEL_COUNT);
+        queue.set_congestion_control(Box::new(Unlimited));
+        let payload_size = 1492 - UDP_HEADER_SIZE - DATAGRAM_HEADER_SIZE - (1 + 2 + 3);
+
+        queue
+            .push(vec![0; payload_size], Reliability::Reliable, 0)
+            .unwrap();
+        queue.flush();
+        let datagram = queue.poll_datagram(now).unwrap();
+        assert_eq!(datagram.packets.len(), 1);
+        assert!(datagram.packets[0].split.is_none());
+        let packet = OnlinePacket::Datagram(datagram);
+        assert_eq!(packet.size() + UDP_HEADER_SIZE, 1492);
+
+        queue
+            .push(vec![0; payload_size + 1], Reliability::Reliable, 0)
+            .unwrap();
+        queue.flush();
+        while let Some(datagram) = queue.poll_datagram(now) {
+            assert!(datagram.packets[0].split.is_some());
+            let packet = OnlinePacket::Datagram(datagram);
+            assert!(packet.size() + UDP_HEADER_SIZE <= 1492);
+        }
     }
 }
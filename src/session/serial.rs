@@ -0,0 +1,34 @@
+/// One more than the largest sequence number or index carried in a `Triad`.
+pub const MODULUS: u32 = 1 << 24;
+
+const MASK: u32 = MODULUS - 1;
+
+/// The 24-bit index after `index`, which wraps to 0 after `0xFF_FFFF`.
+pub fn next(index: u32) -> u32 {
+    index.wrapping_add(1) & MASK
+}
+
+/// How far `to` is ahead of `from`, modulo 2^24.
+pub fn distance(from: u32, to: u32) -> u32 {
+    to.wrapping_sub(from) & MASK
+}
+
+/// Whether `a` was sent before `b`, assuming that the two are less than 2^23 apart.
+pub fn precedes(a: u32, b: u32) -> bool {
+    a != b && distance(a, b) < MODULUS / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(next(0xFF_FFFE), 0xFF_FFFF);
+        assert_eq!(next(0xFF_FFFF), 0);
+        assert_eq!(distance(0xFF_FFFF, 1), 2);
+        assert!(precedes(0xFF_FFFF, 0));
+        assert!(!precedes(0, 0xFF_FFFF));
+        assert!(!precedes(5, 5));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bytes::BytesMut;
+use derive_more::Display;
+use rakrs_protocol::online::inner::{InnerPacket, Split};
+
+/// Bounds on the memory a peer can hold in half-finished split packets.
+#[derive(Clone, Debug)]
+pub struct SplitLimits {
+    /// The maximum number of split IDs being reassembled at the same time.
+    pub max_concurrent: usize,
+    /// The maximum `split_count` of a split packet.
+    pub max_count: u32,
+    /// The maximum number of bytes buffered across all half-finished split packets.
+    pub max_bytes: usize,
+    /// The time after which a half-finished split packet is discarded.
+    pub timeout: Duration,
+}
+
+impl Default for SplitLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            max_count: 128,
+            max_bytes: 1 << 20,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Indicates that a split packet part was rejected.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum SplitError {
+    #[display(fmt = "Split index {} is out of bounds for split count {}", _0, _1)]
+    IndexOutOfBounds(u32, u32),
+    #[display(fmt = "Split count {} exceeds the limit", _0)]
+    TooManyParts(u32),
+    #[display(fmt = "Split count of split ID {} changed", _0)]
+    CountMismatch(u16),
+    #[display(fmt = "Too many concurrent split packets")]
+    TooManySplits,
+    #[display(fmt = "Too many bytes buffered in split packets")]
+    TooManyBytes,
+}
+
+impl std::error::Error for SplitError {}
+
+/// Reassembles `InnerPacket`s that were split because they exceed the MTU.
+#[derive(Default)]
+pub struct SplitAssembler {
+    limits: SplitLimits,
+    pending: HashMap<u16, PendingSplit>,
+    buffered_bytes: usize,
+}
+
+struct PendingSplit {
+    parts: Vec<Option<InnerPacket>>,
+    remaining: u32,
+    started: Instant,
+}
+
+impl SplitAssembler {
+    pub fn new(limits: SplitLimits) -> Self {
+        Self {
+            limits,
+            pending: HashMap::new(),
+            buffered_bytes: 0,
+        }
+    }
+
+    /// Adds a packet part.
+    ///
+    /// Returns the reassembled packet if this was the last missing part. The reassembled packet
+    /// has the reliability of its parts and no split information.
+    pub fn push(
+        &mut self,
+        mut packet: InnerPacket,
+        now: Instant,
+    ) -> Result<Option<InnerPacket>, SplitError> {
+        let Split {
+            split_count,
+            split_id,
+            split_index,
+        } = match packet.split.take() {
+            Some(split) => split,
+            None => return Ok(Some(packet)),
+        };
+
+        if split_count > self.limits.max_count {
+            return Err(SplitError::TooManyParts(split_count));
+        }
+        if split_index >= split_count {
+            return Err(SplitError::IndexOutOfBounds(split_index, split_count));
+        }
+
+        if !self.pending.contains_key(&split_id) {
+            if self.pending.len() >= self.limits.max_concurrent {
+                return Err(SplitError::TooManySplits);
+            }
+            self.pending.insert(
+                split_id,
+                PendingSplit {
+                    parts: vec![None; split_count as usize],
+                    remaining: split_count,
+                    started: now,
+                },
+            );
+        }
+        let pending = self.pending.get_mut(&split_id).unwrap();
+
+        if pending.parts.len() != split_count as usize {
+            return Err(SplitError::CountMismatch(split_id));
+        }
+        let slot = &mut pending.parts[split_index as usize];
+        if slot.is_some() {
+            return Ok(None); // duplicate part
+        }
+        if self.buffered_bytes + packet.buffer.len() > self.limits.max_bytes {
+            return Err(SplitError::TooManyBytes);
+        }
+
+        self.buffered_bytes += packet.buffer.len();
+        *slot = Some(packet);
+        pending.remaining -= 1;
+        if pending.remaining > 0 {
+            return Ok(None);
+        }
+
+        let pending = self.pending.remove(&split_id).unwrap();
+        let mut parts = pending.parts.into_iter().map(Option::unwrap);
+        let mut packet = parts.next().expect("split_count is nonzero");
+        let mut buffer = BytesMut::from(&packet.buffer[..]);
+        for part in parts {
+            buffer.extend_from_slice(&part.buffer);
+        }
+        packet.buffer = buffer.freeze();
+        self.buffered_bytes -= packet.buffer.len();
+        Ok(Some(packet))
+    }
+
+    /// Discards split packets that have not completed within the timeout.
+    pub fn expire(&mut self, now: Instant) {
+        let timeout = self.limits.timeout;
+        let buffered_bytes = &mut self.buffered_bytes;
+        self.pending.retain(|split_id, pending| {
+            if now.duration_since(pending.started) < timeout {
+                return true;
+            }
+            log::debug!("Split packet {} timed out", split_id);
+            for part in pending.parts.iter().flatten() {
+                *buffered_bytes -= part.buffer.len();
+            }
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use rakrs_protocol::online::inner::InnerPacketReliability;
+
+    fn part(split_count: u32, split_id: u16, split_index: u32, buffer: &[u8]) -> InnerPacket {
+        InnerPacket {
+            reliability: InnerPacketReliability::Unreliable,
+            split: Some(Split {
+                split_count,
+                split_id,
+                split_index,
+            }),
+            buffer: Bytes::copy_from_slice(buffer),
+        }
+    }
+
+    #[test]
+    fn test_reassemble() {
+        let now = Instant::now();
+        let mut assembler = SplitAssembler::default();
+        assert_eq!(assembler.push(part(3, 1, 2, b"ef"), now), Ok(None));
+        assert_eq!(assembler.push(part(3, 1, 0, b"ab"), now), Ok(None));
+        assert_eq!(assembler.push(part(3, 1, 0, b"ab"), now), Ok(None));
+        let packet = assembler.push(part(3, 1, 1, b"cd"), now).unwrap().unwrap();
+        assert_eq!(packet.buffer, &b"abcdef"[..]);
+        assert_eq!(packet.split, None);
+        assert_eq!(assembler.buffered_bytes, 0);
+    }
+
+    #[test]
+    fn test_limits() {
+        let now = Instant::now();
+        let mut assembler = SplitAssembler::new(SplitLimits {
+            max_concurrent: 1,
+            max_count: 4,
+            max_bytes: 4,
+            timeout: Duration::from_secs(1),
+        });
+        assert_eq!(
+            assembler.push(part(5, 1, 0, b"a"), now),
+            Err(SplitError::TooManyParts(5))
+        );
+        assert_eq!(
+            assembler.push(part(2, 1, 2, b"a"), now),
+            Err(SplitError::IndexOutOfBounds(2, 2))
+        );
+        assert_eq!(assembler.push(part(4, 1, 0, b"abc"), now), Ok(None));
+        assert_eq!(
+            assembler.push(part(2, 2, 0, b"a"), now),
+            Err(SplitError::TooManySplits)
+        );
+        assert_eq!(
+            assembler.push(part(4, 1, 1, b"de"), now),
+            Err(SplitError::TooManyBytes)
+        );
+        assert_eq!(
+            assembler.push(part(3, 1, 1, b"d"), now),
+            Err(SplitError::CountMismatch(1))
+        );
+    }
+
+    #[test]
+    fn test_expire() {
+        let now = Instant::now();
+        let mut assembler = SplitAssembler::default();
+        assert_eq!(assembler.push(part(2, 1, 0, b"ab"), now), Ok(None));
+        assembler.expire(now + Duration::from_secs(1));
+        assert_eq!(assembler.buffered_bytes, 2);
+        assembler.expire(now + Duration::from_secs(60));
+        assert_eq!(assembler.buffered_bytes, 0);
+        assert_eq!(assembler.push(part(2, 1, 1, b"cd"), now), Ok(None));
+    }
+}
@@ -0,0 +1,140 @@
+use derive_more::Display;
+
+/// The lifecycle stage of a session.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum SessionState {
+    /// The offline handshake has completed, but the online handshake has not.
+    Connecting,
+    /// The session is established and may carry application payloads.
+    Connected,
+    /// The session is being closed locally and waits for pending data to be acknowledged.
+    Disconnecting,
+    /// The session is closed and should be dropped.
+    Disconnected,
+}
+
+/// An event that moves a session to another state.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Transition {
+    /// The peer sent `encap::ConnectionRequest`.
+    ConnectionRequest,
+    /// The peer sent `encap::NewIncomingConnection`.
+    NewIncomingConnection,
+    /// The peer sent `encap::ConnectionRequestAccepted`.
+    ConnectionRequestAccepted,
+    /// The peer sent `encap::DisconnectionNotification`.
+    DisconnectionNotification,
+    /// The session is closed locally.
+    Disconnect,
+    /// The peer acknowledged all packets sent before the local close, or the close timed out.
+    CloseComplete,
+    /// Nothing was received from the peer for too long.
+    Timeout,
+}
+
+/// Explains why a session was closed.
+///
+/// `DisconnectionNotification` carries no reason on the wire, so a notification from the peer is
+/// reported as `ClientQuit` by servers and `ServerShutdown` by clients.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client closed the session.
+    #[display(fmt = "client quit")]
+    ClientQuit,
+    /// The server closed the session because it is stopping.
+    #[display(fmt = "server shutdown")]
+    ServerShutdown,
+    /// The peer stopped responding.
+    #[display(fmt = "timeout")]
+    Timeout,
+    /// The server closed the session of a single client.
+    #[display(fmt = "kicked")]
+    Kicked,
+    /// The peer sent packets that violate the protocol.
+    #[display(fmt = "protocol error")]
+    ProtocolError,
+}
+
+/// Indicates that a transition is not allowed in the current state.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+#[display(fmt = "Unexpected {} in {} state", transition, state)]
+pub struct StateError {
+    pub state: SessionState,
+    pub transition: Transition,
+}
+
+impl std::error::Error for StateError {}
+
+impl SessionState {
+    /// Computes the state after `transition`.
+    pub fn transition(self, transition: Transition) -> Result<Self, StateError> {
+        use SessionState::*;
+
+        let next = match (self, transition) {
+            (Connecting, Transition::ConnectionRequest) => Connecting,
+            (Connecting, Transition::NewIncomingConnection)
+            | (Connecting, Transition::ConnectionRequestAccepted) => Connected,
+            (Connecting, Transition::DisconnectionNotification)
+            | (Connected, Transition::DisconnectionNotification)
+            | (Disconnecting, Transition::DisconnectionNotification) => Disconnected,
+            (Connecting, Transition::Disconnect) | (Connected, Transition::Disconnect) => {
+                Disconnecting
+            }
+            (Disconnecting, Transition::CloseComplete) => Disconnected,
+            (Connecting, Transition::Timeout) | (Connected, Transition::Timeout) => Disconnected,
+            (state, transition) => return Err(StateError { state, transition }),
+        };
+        Ok(next)
+    }
+
+    /// Checks whether the session may carry application payloads.
+    pub fn is_connected(self) -> bool {
+        self == SessionState::Connected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle() {
+        let state = SessionState::Connecting;
+        let state = state.transition(Transition::ConnectionRequest).unwrap();
+        assert_eq!(state, SessionState::Connecting);
+        let state = state.transition(Transition::NewIncomingConnection).unwrap();
+        assert_eq!(state, SessionState::Connected);
+        let state = state.transition(Transition::Disconnect).unwrap();
+        assert_eq!(state, SessionState::Disconnecting);
+        assert_eq!(
+            state.transition(Transition::CloseComplete),
+            Ok(SessionState::Disconnected)
+        );
+        let state = state
+            .transition(Transition::DisconnectionNotification)
+            .unwrap();
+        assert_eq!(state, SessionState::Disconnected);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = SessionState::Connected
+            .transition(Transition::NewIncomingConnection)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            StateError {
+                state: SessionState::Connected,
+                transition: Transition::NewIncomingConnection,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Unexpected NewIncomingConnection in Connected state"
+        );
+
+        assert!(SessionState::Disconnected
+            .transition(Transition::DisconnectionNotification)
+            .is_err());
+    }
+}
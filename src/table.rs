@@ -0,0 +1,205 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use rakrs_io::CanIo;
+use rakrs_protocol::offline::OfflinePacket;
+use rakrs_protocol::online::OnlinePacket;
+use tokio::sync::mpsc;
+
+use crate::config::ServerConfig;
+use crate::connection::{Command, Connection};
+use crate::handshake::Handshake;
+use crate::ping::{PingResponder, ServerStatus};
+use crate::server::ServerEvent;
+use crate::session::{Session, SessionEvent, SessionState};
+
+/// Owns all sessions of a server, keyed by the remote address.
+pub struct SessionTable {
+    handshake: Handshake,
+    ping: PingResponder,
+    config: ServerConfig,
+    sessions: HashMap<SocketAddr, Session>,
+    /// Encoded packets that are not sent by a session, either because they are offline replies or
+    /// because their session has been dropped.
+    outbox: VecDeque<(SocketAddr, Vec<u8>)>,
+    dispatcher: Dispatcher,
+    commands: mpsc::UnboundedReceiver<Command>,
+}
+
+/// Converts session events into events for the application.
+struct Dispatcher {
+    events: VecDeque<ServerEvent>,
+    command_sender: mpsc::UnboundedSender<Command>,
+    channel_count: u8,
+}
+
+impl SessionTable {
+    pub fn new(handshake: Handshake, ping: PingResponder, config: ServerConfig) -> Self {
+        let (command_sender, commands) = mpsc::unbounded_channel();
+        let channel_count = config.session().channel_count;
+        Self {
+            handshake,
+            ping,
+            config,
+            sessions: HashMap::new(),
+            outbox: VecDeque::new(),
+            dispatcher: Dispatcher {
+                events: VecDeque::new(),
+                command_sender,
+                channel_count,
+            },
+            commands,
+        }
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        ServerStatus {
+            online: self.sessions.len(),
+            max_connections: self.config.max_connections(),
+        }
+    }
+
+    pub fn is_online(&self, addr: &SocketAddr) -> bool {
+        self.sessions.contains_key(addr)
+    }
+
+    /// Performs periodic work on all sessions and removes the closed ones.
+    pub fn tick(&mut self, now: Instant) {
+        let mut closed = vec![];
+        for (&addr, session) in &mut self.sessions {
+            session.tick(now);
+            self.dispatcher.dispatch(addr, session);
+            if session.state() == SessionState::Disconnected {
+                closed.push(addr);
+            }
+        }
+        for addr in closed {
+            self.drop_session(addr, now);
+        }
+    }
+
+    /// Applies all pending commands from `Connection` handles.
+    ///
+    /// Returns whether any command was applied. If none is pending, the task in `cx` is woken up
+    /// when one arrives.
+    pub fn poll_commands(&mut self, cx: &mut Context<'_>) -> bool {
+        let mut applied = false;
+        // the table holds a sender, so the stream never ends
+        while let Poll::Ready(Some(command)) = self.commands.poll_recv(cx) {
+            self.apply(command);
+            applied = true;
+        }
+        applied
+    }
+
+    pub fn poll_send(&mut self, now: Instant) -> Option<(SocketAddr, Vec<u8>)> {
+        if let Some(pair) = self.outbox.pop_front() {
+            return Some(pair);
+        }
+
+        for (&addr, session) in &mut self.sessions {
+            if let Some(packet) = session.poll_send(now) {
+                return Some((addr, packet.encode_to_vec()));
+            }
+        }
+        None
+    }
+
+    /// Removes a disconnected session after queueing its final ACKs.
+    fn drop_session(&mut self, addr: SocketAddr, now: Instant) {
+        if let Some(mut session) = self.sessions.remove(&addr) {
+            session.tick(now);
+            while let Some(packet) = session.poll_send(now) {
+                if let OnlinePacket::Datagram(_) = packet {
+                    continue; // nobody is left to resend it
+                }
+                self.outbox.push_back((addr, packet.encode_to_vec()));
+            }
+        }
+    }
+
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::Send {
+                address,
+                buffer,
+                reliability,
+                order_channel,
+                receipt_id,
+            } => {
+                if let Some(session) = self.sessions.get_mut(&address) {
+                    if let Err(err) =
+                        session.send_with_receipt(buffer, reliability, order_channel, receipt_id)
+                    {
+                        log::warn!("Failed to send to {}: {}", &address, err);
+                    }
+                }
+            }
+            Command::Close { address, reason } => {
+                if let Some(session) = self.sessions.get_mut(&address) {
+                    if let Err(err) = session.close(reason, Instant::now()) {
+                        log::debug!("Failed to close {}: {}", &address, err);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn poll_event(&mut self) -> Option<ServerEvent> {
+        self.dispatcher.events.pop_front()
+    }
+
+    pub fn push_online(&mut self, addr: SocketAddr, packet: OnlinePacket) {
+        let session = match self.sessions.get_mut(&addr) {
+            Some(session) => session,
+            None => return,
+        };
+        let now = Instant::now();
+        session.handle(packet, now);
+        self.dispatcher.dispatch(addr, session);
+        if session.state() == SessionState::Disconnected {
+            self.drop_session(addr, now);
+        }
+    }
+
+    pub fn push_offline(&mut self, addr: SocketAddr, packet: OfflinePacket) {
+        let online = self.sessions.len();
+        let reply = if let Some((reply, session)) =
+            self.handshake
+                .respond(addr, &packet, online, Instant::now())
+        {
+            if let Some(session) = session {
+                self.sessions.insert(addr, session);
+            }
+            reply
+        } else if let Some(reply) = self.ping.respond(&packet, &self.status()) {
+            reply
+        } else {
+            return;
+        };
+
+        self.outbox.push_back((addr, reply.encode_to_vec()));
+    }
+}
+
+impl Dispatcher {
+    /// Queues all pending events of a session for the application.
+    fn dispatch(&mut self, addr: SocketAddr, session: &mut Session) {
+        while let Some(event) = session.poll_event() {
+            let event = match event {
+                SessionEvent::Connected => ServerEvent::Connected(Connection::new(
+                    addr,
+                    self.channel_count,
+                    self.command_sender.clone(),
+                )),
+                SessionEvent::Message(buf) => ServerEvent::Message(addr, buf),
+                SessionEvent::Disconnected(reason) => ServerEvent::Disconnected(addr, reason),
+                SessionEvent::Receipt(receipt) => ServerEvent::Receipt(addr, receipt),
+                SessionEvent::Pong(stats) => ServerEvent::PingStats(addr, stats),
+            };
+            self.events.push_back(event);
+        }
+    }
+}
@@ -13,19 +13,21 @@ macro_rules! canio_ok {
             let mut actual = ::std::vec::Vec::<u8>::new();
             ::rakrs_io::CanIo::write(&$expr, &mut actual).expect("Panic writing data");
             assert_eq!(vec![$($buf),*], actual);
+            assert_eq!(actual.len(), ::rakrs_io::CanIo::size(&$expr));
+            assert_eq!(actual, ::rakrs_io::CanIo::encode_to_vec(&$expr));
         }
     };
 }
 
 #[macro_export]
 macro_rules! canio_err_read {
-    ($name:ident: $ty:ty => $err:literal; $($buf:literal),* $(,)?) => {
+    ($name:ident: $ty:ty => $kind:expr; $($buf:literal),* $(,)?) => {
         #[test]
         pub fn $name() {
-            let actual = <$ty as ::rakrs_io::CanIo>::read(::std::io::Cursor::new(::std::vec![$($buf),*]));
+            let actual = <$ty as ::rakrs_io::CanIo>::decode(&[$($buf),*]);
             match actual {
                 Ok(_) => panic!("Read invalid data as valid"),
-                Err(err) => assert_eq!($err, err.to_string().as_str()),
+                Err(err) => assert_eq!($kind, err.kind()),
             }
         }
     };